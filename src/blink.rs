@@ -0,0 +1,123 @@
+use std::thread::sleep;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Result};
+
+use crate::qlight::{LightCommandSet, LightMode, SoundMode};
+use crate::{record, update_all, Lights};
+
+/// Copies `lightset` with every blinking color replaced by `blink_as` and
+/// everything else left alone.
+fn blink_phase(lightset: &LightCommandSet, blink_as: LightMode) -> LightCommandSet {
+    let phase = |mode: LightMode| {
+        if mode == LightMode::Blink {
+            blink_as
+        } else {
+            LightMode::Ignore
+        }
+    };
+
+    LightCommandSet {
+        red: phase(lightset.red),
+        yellow: phase(lightset.yellow),
+        green: phase(lightset.green),
+        blue: phase(lightset.blue),
+        white: phase(lightset.white),
+        sound: SoundMode::Ignore,
+    }
+}
+
+/// Blinks colors by writing on/off from software instead of using the
+/// towers' own blink timers, which free-run and drift apart. The phase is
+/// taken from the system clock, so separate processes (or NTP synced hosts)
+/// flash in step as well. Runs until interrupted.
+pub fn sync_blink(lights: &mut Lights, lightset: &LightCommandSet, period: Duration) -> Result<()> {
+    let half = period.as_millis() / 2;
+    if half == 0 {
+        bail!("Blink period must be at least 2ms");
+    }
+
+    let modes = [
+        lightset.red,
+        lightset.yellow,
+        lightset.green,
+        lightset.blue,
+        lightset.white,
+    ];
+    if !modes.contains(&LightMode::Blink) {
+        bail!("Nothing to blink, give at least one color as [color]:blink");
+    }
+
+    let on = blink_phase(lightset, LightMode::On);
+    let off = blink_phase(lightset, LightMode::Off);
+
+    // Write everything once, then only touch the blinking colors.
+    let mut first = lightset.clone();
+    for mode in [
+        &mut first.red,
+        &mut first.yellow,
+        &mut first.green,
+        &mut first.blue,
+        &mut first.white,
+    ] {
+        if *mode == LightMode::Blink {
+            *mode = LightMode::Off;
+        }
+    }
+    // History and stats get what was asked for, once, rather than every
+    // phase.
+    lights.recording = false;
+    update_all(lights, &first)?;
+    for light in &lights.opened {
+        record(&light.info().path, lightset);
+    }
+
+    loop {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let next = (now / half + 1) * half;
+        sleep(Duration::from_millis((next - now) as u64));
+
+        let phase = if (next / half) % 2 == 1 { &off } else { &on };
+        update_all(lights, phase)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qlight::Color;
+
+    #[test]
+    fn phase_only_touches_blinking_colors() {
+        let lightset = LightCommandSet::default_off()
+            .with(Color::Red, LightMode::Blink)
+            .with(Color::Green, LightMode::On)
+            .with(Color::White, LightMode::Blink)
+            .with_sound(SoundMode::Noise2);
+
+        let expected = LightCommandSet::default()
+            .with(Color::Red, LightMode::On)
+            .with(Color::White, LightMode::On);
+        assert_eq!(
+            blink_phase(&lightset, LightMode::On).to_report(),
+            expected.to_report()
+        );
+
+        let expected = LightCommandSet::default()
+            .with(Color::Red, LightMode::Off)
+            .with(Color::White, LightMode::Off);
+        assert_eq!(
+            blink_phase(&lightset, LightMode::Off).to_report(),
+            expected.to_report()
+        );
+    }
+
+    #[test]
+    fn phase_without_blinking_colors_is_empty() {
+        let lightset = LightCommandSet::default_off().with(Color::Blue, LightMode::On);
+        assert_eq!(
+            blink_phase(&lightset, LightMode::On).to_report(),
+            LightCommandSet::default().to_report()
+        );
+    }
+}
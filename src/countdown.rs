@@ -0,0 +1,73 @@
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::qlight::{Color, LightCommandSet, LightMode, SoundMode};
+use crate::{parse_duration, update_all, Lights, Picker};
+
+/// How long the buzzer sounds for when `--beep` is set.
+const BEEP_LENGTH: Duration = Duration::from_secs(3);
+
+/// Count down a timer, going green, then yellow, then red when time is up
+#[derive(Parser, Debug)]
+pub struct CountdownArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// How long to count down for, e.g. 90s, 10m or 1h30m
+    #[arg(value_parser = parse_duration)]
+    duration: Duration,
+
+    /// Switch to yellow once this much time is left.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration)]
+    warn: Option<Duration>,
+
+    /// Blink red once time is up instead of holding it.
+    #[arg(long)]
+    blink: bool,
+
+    /// Sound the buzzer briefly once time is up.
+    #[arg(long)]
+    beep: bool,
+}
+
+fn show(lights: &mut Lights, color: Color, light_mode: LightMode, sound: SoundMode) -> Result<()> {
+    let lightset = LightCommandSet::default_off()
+        .with(color, light_mode)
+        .with_sound(sound);
+    update_all(lights, &lightset)
+}
+
+fn sleep_until(deadline: Instant) {
+    sleep(deadline.saturating_duration_since(Instant::now()));
+}
+
+pub fn countdown(args: CountdownArgs) -> Result<()> {
+    let mut lights = args.picker.open()?;
+
+    let end = Instant::now() + args.duration;
+    let warn = args.warn.unwrap_or(Duration::ZERO);
+
+    if args.duration > warn {
+        show(&mut lights, Color::Green, LightMode::On, SoundMode::Off)?;
+        sleep_until(end - warn);
+    }
+
+    if !warn.is_zero() {
+        show(&mut lights, Color::Yellow, LightMode::On, SoundMode::Off)?;
+        sleep_until(end);
+    }
+
+    let red = if args.blink {
+        LightMode::Blink
+    } else {
+        LightMode::On
+    };
+    if args.beep {
+        show(&mut lights, Color::Red, red, SoundMode::Noise1)?;
+        sleep(BEEP_LENGTH);
+    }
+    show(&mut lights, Color::Red, red, SoundMode::Off)
+}
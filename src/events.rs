@@ -0,0 +1,108 @@
+//! Machine readable feed of what the CLI does, one JSON object per line on
+//! stdout. Off unless `--events` is passed.
+//!
+//! ```text
+//! {"time":1700000000123,"event":"state","path":"...","red":"on","yellow":"off",...,"sound":"off"}
+//! {"time":1700000000456,"event":"error","path":"...","message":"..."}
+//! ```
+
+use std::fmt::{Display, Write as _};
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::qlight::LightCommandSet;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Escapes `s` for use inside a JSON string literal.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes `{"time":...,"event":"<event>",<fields>}`, where `fields` is
+/// already JSON.
+fn emit(event: &str, fields: &str) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+
+    // Nothing sensible to do if stdout has gone away.
+    let _ = writeln!(
+        std::io::stdout().lock(),
+        "{{\"time\":{},\"event\":\"{}\",{}}}",
+        time,
+        event,
+        fields
+    );
+}
+
+pub fn state(path: &str, lightset: &LightCommandSet) {
+    emit(
+        "state",
+        &format!(
+            "\"path\":\"{}\",\"red\":\"{}\",\"yellow\":\"{}\",\"green\":\"{}\",\"blue\":\"{}\",\"white\":\"{}\",\"sound\":\"{}\"",
+            escape(path),
+            lightset.red.name(),
+            lightset.yellow.name(),
+            lightset.green.name(),
+            lightset.blue.name(),
+            lightset.white.name(),
+            lightset.sound.name(),
+        ),
+    );
+}
+
+pub fn error(path: Option<&str>, error: &dyn Display) {
+    let message = format!("\"message\":\"{}\"", escape(&error.to_string()));
+    match path {
+        Some(path) => emit(
+            "error",
+            &format!("\"path\":\"{}\",{}", escape(path), message),
+        ),
+        None => emit("error", &message),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_json_specials() {
+        let cases = [
+            ("/dev/hidraw0", "/dev/hidraw0"),
+            (r#"say "hi""#, r#"say \"hi\""#),
+            (r"\\?\hid#vid_04d8", r"\\\\?\\hid#vid_04d8"),
+            ("a\nb\r\tc", r"a\nb\r\tc"),
+            ("\u{1}\u{1f}\u{7f}", r"\u0001\u001f\u007f"),
+            ("caf\u{e9} \u{2603}", "caf\u{e9} \u{2603}"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(escape(input), expected, "{:?}", input);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::{BufRead, BufReader};
+use std::process::{ChildStdout, Command, Stdio};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use crate::qlight::LightCommandSet;
+use crate::{parse_command, update_all, Lights, Picker};
+
+/// Run a program and apply every line it prints as a set of commands
+///
+/// Each line holds [color]:[state] commands in the same format as `set`,
+/// optionally starting with `reset` to turn unspecified colors off, e.g.
+/// `reset red:blink`. This lets any script or integration drive the lights
+/// without touching this crate.
+#[derive(Parser, Debug)]
+pub struct ExecArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// The program to run, followed by its arguments
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+}
+
+pub fn parse_line(line: &str) -> Result<LightCommandSet> {
+    let mut words = line.split_whitespace().peekable();
+
+    let mut lightset = if words.next_if_eq(&"reset").is_some() {
+        LightCommandSet::default_off()
+    } else {
+        LightCommandSet::default()
+    };
+
+    for word in words {
+        let (color, light_mode) = parse_command(word)?;
+        lightset.set(color, light_mode);
+    }
+    Ok(lightset)
+}
+
+fn apply_lines(stdout: ChildStdout, lights: &mut Lights) -> Result<()> {
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match parse_line(&line) {
+            Ok(lightset) => update_all(lights, &lightset)?,
+            Err(e) => eprintln!("Ignoring line {:?}: {}", line, e),
+        }
+    }
+    Ok(())
+}
+
+pub fn exec(args: ExecArgs) -> Result<()> {
+    let mut lights = args.picker.open()?;
+
+    let mut child = Command::new(&args.command[0])
+        .args(&args.command[1..])
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}", args.command[0]))?;
+
+    let stdout = child.stdout.take().expect("stdout is piped");
+    if let Err(e) = apply_lines(stdout, &mut lights) {
+        // Don't leave the program running with nothing reading its output.
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("{} exited with {}", args.command[0], status);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qlight::{Color, LightMode};
+
+    #[test]
+    fn parses_commands() {
+        let lightset = parse_line("red:blink  green:on").unwrap();
+        assert_eq!(lightset.red, LightMode::Blink);
+        assert_eq!(lightset.green, LightMode::On);
+        assert_eq!(lightset.yellow, LightMode::Ignore);
+    }
+
+    #[test]
+    fn reset_turns_the_rest_off() {
+        let lightset = parse_line("reset yellow:on").unwrap();
+        assert_eq!(
+            lightset.to_report(),
+            LightCommandSet::default_off()
+                .with(Color::Yellow, LightMode::On)
+                .to_report()
+        );
+
+        let lightset = parse_line("reset").unwrap();
+        assert_eq!(
+            lightset.to_report(),
+            LightCommandSet::default_off().to_report()
+        );
+    }
+
+    #[test]
+    fn reset_only_counts_first() {
+        assert!(parse_line("red:on reset").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_words() {
+        for line in ["purple:on", "red:dim", "red", "red:on:off"] {
+            assert!(parse_line(line).is_err(), "{}", line);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Exit codes, so scripts can tell failures apart without reading stderr.
+//!
+//! | Code | Meaning                                          |
+//! |------|--------------------------------------------------|
+//! | 0    | Success                                          |
+//! | 1    | Any other error                                  |
+//! | 2    | Bad command line (reported by clap)              |
+//! | 3    | No matching light is connected                   |
+//! | 4    | Permission denied opening a light                |
+//! | 5    | Some lights were updated but others failed       |
+//! | 6    | A light didn't respond within `--timeout`        |
+
+use std::process::ExitCode;
+
+use crate::qlight::LightError;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Kind {
+    NoDevice = 3,
+    PermissionDenied = 4,
+    PartialFailure = 5,
+    Timeout = 6,
+}
+
+/// An error with its own exit code. Anything else exits with 1.
+#[derive(Debug)]
+pub struct Failure {
+    kind: Kind,
+    message: String,
+}
+
+impl Failure {
+    pub fn new(kind: Kind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// Turns a failure to open a light into a [`Failure`] when it was down to
+/// permissions. hidapi only gives us the platform's message to go on.
+pub fn open_error(path: &str, error: LightError) -> anyhow::Error {
+    let message = error.to_string();
+    if message.contains("Permission denied") || message.contains("Access is denied") {
+        Failure::new(
+            Kind::PermissionDenied,
+            format!("Failed to open {}: {}", path, message),
+        )
+        .into()
+    } else {
+        anyhow::Error::from(error).context(format!("Failed to open {}", path))
+    }
+}
+
+pub fn code(error: &anyhow::Error) -> ExitCode {
+    let kind = error.chain().find_map(|e| {
+        if let Some(failure) = e.downcast_ref::<Failure>() {
+            Some(failure.kind)
+        } else if let Some(LightError::Timeout(_)) = e.downcast_ref::<LightError>() {
+            Some(Kind::Timeout)
+        } else {
+            None
+        }
+    });
+
+    match kind {
+        Some(kind) => ExitCode::from(kind as u8),
+        None => ExitCode::FAILURE,
+    }
+}
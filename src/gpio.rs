@@ -0,0 +1,309 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Parser};
+
+use crate::exec::parse_line;
+use crate::qlight::LightCommandSet;
+use crate::verbosity::detail;
+use crate::{parse_duration, update_all, Picker};
+
+// From the v2 GPIO character device uAPI in linux/gpio.h, added in Linux
+// 5.10.
+const GPIO_V2_LINE_FLAG_ACTIVE_LOW: u64 = 1 << 1;
+const GPIO_V2_LINE_FLAG_INPUT: u64 = 1 << 2;
+const GPIO_V2_LINE_FLAG_EDGE_RISING: u64 = 1 << 4;
+const GPIO_V2_LINE_FLAG_EDGE_FALLING: u64 = 1 << 5;
+const GPIO_V2_LINE_FLAG_BIAS_PULL_UP: u64 = 1 << 8;
+const GPIO_V2_LINE_FLAG_BIAS_PULL_DOWN: u64 = 1 << 9;
+const GPIO_V2_LINE_ATTR_ID_DEBOUNCE: u32 = 3;
+/// `_IOWR(0xB4, 0x07, struct gpio_v2_line_request)`
+const GPIO_V2_GET_LINE_IOCTL: u32 = 0xC250_B407;
+/// `_IOWR(0xB4, 0x0E, struct gpio_v2_line_values)`
+const GPIO_V2_LINE_GET_VALUES_IOCTL: u32 = 0xC010_B40E;
+/// `sizeof(struct gpio_v2_line_event)`, one of which is read per edge.
+const LINE_EVENT_LEN: usize = 48;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+union AttributeValue {
+    flags: u64,
+    debounce_period_us: u32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct LineConfigAttribute {
+    id: u32,
+    padding: u32,
+    value: AttributeValue,
+    /// Which of the requested lines the attribute applies to.
+    mask: u64,
+}
+
+#[repr(C)]
+struct LineConfig {
+    flags: u64,
+    num_attrs: u32,
+    padding: [u32; 5],
+    attrs: [LineConfigAttribute; 10],
+}
+
+#[repr(C)]
+struct LineRequest {
+    offsets: [u32; 64],
+    consumer: [u8; 32],
+    config: LineConfig,
+    num_lines: u32,
+    event_buffer_size: u32,
+    padding: [u32; 5],
+    fd: libc::c_int,
+}
+
+#[repr(C)]
+struct LineValues {
+    bits: u64,
+    mask: u64,
+}
+
+/// Drive the lights from a GPIO input (Linux 5.10 or later)
+///
+/// Watches one line of a GPIO chip, such as a Raspberry Pi's header, and
+/// applies commands whenever it goes high or low, e.g. `qlight gpio --all
+/// --line 17 --pull-up --active-low --high 'reset red:on' --low 'reset
+/// green:on'` for a door contact wired between GPIO 17 and ground. The
+/// line's current level is shown straight away.
+#[derive(Parser, Debug)]
+#[command(group(
+    ArgGroup::new("commands")
+        .required(true)
+        .multiple(true)
+        .args(["high", "low"])
+))]
+pub struct GpioArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// The GPIO chip the line is on.
+    #[arg(long, default_value = "/dev/gpiochip0")]
+    chip: PathBuf,
+
+    /// The line to watch, by its offset on the chip. On a Raspberry Pi
+    /// that's the BCM GPIO number, not the header pin.
+    #[arg(long)]
+    line: u32,
+
+    /// Commands to apply when the line goes high, in the same format as
+    /// `exec` reads, like `reset red:on`.
+    #[arg(long, value_name = "COMMANDS", value_parser = parse_line)]
+    high: Option<LightCommandSet>,
+
+    /// Commands to apply when the line goes low.
+    #[arg(long, value_name = "COMMANDS", value_parser = parse_line)]
+    low: Option<LightCommandSet>,
+
+    /// Treat a low level as high and the other way around, for switches
+    /// that pull the line to ground when closed.
+    #[arg(long)]
+    active_low: bool,
+
+    /// Turn on the line's pull-up resistor.
+    #[arg(long, conflicts_with = "pull_down")]
+    pull_up: bool,
+
+    /// Turn on the line's pull-down resistor.
+    #[arg(long)]
+    pull_down: bool,
+
+    /// How long the line has to hold a new level before it counts, to ride
+    /// out switch bounce. 0 turns debouncing off.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "50ms")]
+    debounce: Duration,
+}
+
+impl GpioArgs {
+    fn line_flags(&self) -> u64 {
+        let mut flags = GPIO_V2_LINE_FLAG_INPUT
+            | GPIO_V2_LINE_FLAG_EDGE_RISING
+            | GPIO_V2_LINE_FLAG_EDGE_FALLING;
+        if self.active_low {
+            flags |= GPIO_V2_LINE_FLAG_ACTIVE_LOW;
+        }
+        if self.pull_up {
+            flags |= GPIO_V2_LINE_FLAG_BIAS_PULL_UP;
+        }
+        if self.pull_down {
+            flags |= GPIO_V2_LINE_FLAG_BIAS_PULL_DOWN;
+        }
+        flags
+    }
+
+    fn request(&self) -> Result<LineRequest> {
+        let Ok(debounce_us) = u32::try_from(self.debounce.as_micros()) else {
+            bail!(
+                "--debounce can be at most {:?}",
+                Duration::from_micros(u32::MAX.into())
+            );
+        };
+
+        // SAFETY: the request is all integers, for which zero is valid.
+        let mut request: LineRequest = unsafe { std::mem::zeroed() };
+        request.offsets[0] = self.line;
+        request.num_lines = 1;
+        request.consumer[..6].copy_from_slice(b"qlight");
+        request.config.flags = self.line_flags();
+        if debounce_us > 0 {
+            // The kernel falls back to debouncing in software when the chip
+            // can't do it.
+            request.config.attrs[0] = LineConfigAttribute {
+                id: GPIO_V2_LINE_ATTR_ID_DEBOUNCE,
+                padding: 0,
+                value: AttributeValue {
+                    debounce_period_us: debounce_us,
+                },
+                mask: 1,
+            };
+            request.config.num_attrs = 1;
+        }
+        Ok(request)
+    }
+}
+
+fn ioctl<T>(fd: &impl AsRawFd, request: u32, arg: &mut T) -> std::io::Result<()> {
+    // SAFETY: every request used here reads and writes a `T` matching the
+    // kernel's struct for it, and the fd is open for as long as it's
+    // borrowed.
+    if unsafe { libc::ioctl(fd.as_raw_fd(), request as _, arg as *mut T) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Requests the line from the chip. Reading the returned file gives a line
+/// event per edge.
+fn request_line(chip: &File, request: &mut LineRequest) -> std::io::Result<File> {
+    ioctl(chip, GPIO_V2_GET_LINE_IOCTL, request)?;
+    // SAFETY: the kernel just handed this fd over, and nothing else owns it.
+    Ok(unsafe { File::from_raw_fd(request.fd) })
+}
+
+/// The line's level, after `--active-low` is taken into account.
+fn read_level(line: &File) -> std::io::Result<bool> {
+    let mut values = LineValues { bits: 0, mask: 1 };
+    ioctl(line, GPIO_V2_LINE_GET_VALUES_IOCTL, &mut values)?;
+    Ok(values.bits & 1 != 0)
+}
+
+pub fn gpio(args: GpioArgs) -> Result<()> {
+    let mut request = args.request()?;
+
+    let mut lights = args.picker.open()?;
+
+    let chip = File::open(&args.chip)
+        .with_context(|| format!("Failed to open {}", args.chip.display()))?;
+    let mut line = request_line(&chip, &mut request).with_context(|| {
+        format!(
+            "Failed to watch line {} of {}",
+            args.line,
+            args.chip.display()
+        )
+    })?;
+    let context = || format!("Failed to read line {}", args.line);
+
+    let mut shown = None;
+    let mut event = [0; LINE_EVENT_LEN];
+    loop {
+        let level = read_level(&line).with_context(context)?;
+        if shown != Some(level) {
+            detail!(
+                "Line {} is {}",
+                args.line,
+                if level { "high" } else { "low" }
+            );
+            let lightset = if level { &args.high } else { &args.low };
+            if let Some(lightset) = lightset {
+                update_all(&mut lights, lightset)?;
+            }
+            shown = Some(level);
+        }
+
+        // Wait for the next edge, which has already been debounced.
+        line.read_exact(&mut event).with_context(context)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(extra: &[&str]) -> GpioArgs {
+        let mut argv = vec!["gpio", "--all", "--line", "17", "--high", "red:on"];
+        argv.extend(extra);
+        GpioArgs::try_parse_from(argv).unwrap()
+    }
+
+    #[test]
+    fn structs_match_the_kernel() {
+        // The sizes are baked into the ioctl numbers.
+        let size = |ioctl: u32| ((ioctl >> 16) & 0x3fff) as usize;
+        assert_eq!(
+            std::mem::size_of::<LineRequest>(),
+            size(GPIO_V2_GET_LINE_IOCTL)
+        );
+        assert_eq!(
+            std::mem::size_of::<LineValues>(),
+            size(GPIO_V2_LINE_GET_VALUES_IOCTL)
+        );
+        assert_eq!(std::mem::size_of::<LineConfigAttribute>(), 24);
+        assert_eq!(std::mem::size_of::<LineConfig>(), 272);
+    }
+
+    #[test]
+    fn builds_line_flags() {
+        let edges = GPIO_V2_LINE_FLAG_EDGE_RISING | GPIO_V2_LINE_FLAG_EDGE_FALLING;
+        assert_eq!(args(&[]).line_flags(), GPIO_V2_LINE_FLAG_INPUT | edges);
+        assert_eq!(
+            args(&["--active-low", "--pull-up"]).line_flags(),
+            GPIO_V2_LINE_FLAG_INPUT
+                | edges
+                | GPIO_V2_LINE_FLAG_ACTIVE_LOW
+                | GPIO_V2_LINE_FLAG_BIAS_PULL_UP
+        );
+    }
+
+    #[test]
+    fn asks_the_kernel_to_debounce() {
+        let request = args(&[]).request().unwrap();
+        assert_eq!(request.offsets[0], 17);
+        assert_eq!(request.num_lines, 1);
+        assert_eq!(request.config.num_attrs, 1);
+        let attr = request.config.attrs[0];
+        assert_eq!(attr.id, GPIO_V2_LINE_ATTR_ID_DEBOUNCE);
+        assert_eq!(unsafe { attr.value.debounce_period_us }, 50_000);
+
+        let request = args(&["--debounce", "0"]).request().unwrap();
+        assert_eq!(request.config.num_attrs, 0);
+
+        assert!(args(&["--debounce", "2h"]).request().is_err());
+    }
+
+    #[test]
+    fn needs_commands_for_at_least_one_level() {
+        assert!(GpioArgs::try_parse_from(["gpio", "--all", "--line", "17"]).is_err());
+        assert!(GpioArgs::try_parse_from([
+            "gpio",
+            "--all",
+            "--line",
+            "17",
+            "--pull-up",
+            "--pull-down",
+            "--low",
+            "red:on"
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Append-only log of every state written to a light, for working out after
+//! the fact why a light showed what it did. Each write adds one tab
+//! separated line to `history.log` in the [state directory](state::dir):
+//!
+//! ```text
+//! <unix ms>\t<user>\t<command>\t<path>\tred:on yellow:off ... sound:off
+//! ```
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use crate::qlight::LightCommandSet;
+use crate::state;
+
+const FILE: &str = "history.log";
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Show the log of states written to lights, oldest first
+///
+/// Every command that writes to a light adds to it, along with who ran
+/// what. It's kept in history.log under $QLIGHT_STATE_DIR, or the usual
+/// per-user state directory for the platform.
+#[derive(Parser, Debug)]
+pub struct HistoryArgs {
+    /// Only show writes to this light.
+    #[arg(long, value_name = "PATH")]
+    path: Option<String>,
+
+    /// Show at most this many of the latest writes.
+    #[arg(long, short = 'n', default_value_t = 20)]
+    limit: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Entry {
+    time_ms: u64,
+    user: String,
+    command: String,
+    path: String,
+    state: String,
+}
+
+impl Entry {
+    fn to_line(&self) -> String {
+        // Keep every entry on one line with the right number of fields.
+        let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.time_ms,
+            clean(&self.user),
+            clean(&self.command),
+            clean(&self.path),
+            clean(&self.state)
+        )
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let entry = Self {
+            time_ms: fields.next()?.parse().ok()?,
+            user: fields.next()?.to_string(),
+            command: fields.next()?.to_string(),
+            path: fields.next()?.to_string(),
+            state: fields.next()?.to_string(),
+        };
+        fields.next().is_none().then_some(entry)
+    }
+}
+
+fn append(entry: &Entry) -> Result<()> {
+    let dir = state::dir()?;
+    std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(FILE);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    // One write per entry, so concurrent processes don't interleave lines.
+    file.write_all(entry.to_line().as_bytes())?;
+    Ok(())
+}
+
+/// Adds a write to the history. Not being able to keep history shouldn't
+/// stop the lights from working, so that's only warned about, once.
+pub fn record(path: &str, lightset: &LightCommandSet) {
+    let time_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    let entry = Entry {
+        time_ms,
+        user: state::user(),
+        command: state::command().to_string(),
+        path: path.to_string(),
+        state: lightset.to_string(),
+    };
+
+    if let Err(e) = append(&entry) {
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            eprintln!("Not recording history: {:#}", e);
+        }
+    }
+}
+
+pub fn history(args: HistoryArgs) -> Result<()> {
+    let path = state::dir()?.join(FILE);
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read {}", path.display()));
+        }
+    };
+
+    let entries: Vec<Entry> = contents
+        .lines()
+        .filter_map(Entry::parse)
+        .filter(|entry| args.path.as_ref().is_none_or(|path| &entry.path == path))
+        .collect();
+
+    let mut stdout = std::io::stdout().lock();
+    for entry in &entries[entries.len().saturating_sub(args.limit)..] {
+        writeln!(
+            stdout,
+            "{}.{:03}  {}  {}  {}  {}",
+            state::format_time(entry.time_ms / 1000),
+            entry.time_ms % 1000,
+            entry.user,
+            entry.command,
+            entry.path,
+            entry.state
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            time_ms: 1_700_000_000_123,
+            user: "ops".to_string(),
+            command: "set".to_string(),
+            path: "/dev/hidraw0".to_string(),
+            state: "red:on yellow:off green:off blue:off white:off sound:off".to_string(),
+        }
+    }
+
+    #[test]
+    fn round_trips_entries() {
+        let line = entry().to_line();
+        assert_eq!(
+            line,
+            "1700000000123\tops\tset\t/dev/hidraw0\tred:on yellow:off green:off blue:off white:off sound:off\n"
+        );
+        assert_eq!(Entry::parse(line.trim_end()), Some(entry()));
+    }
+
+    #[test]
+    fn keeps_awkward_fields_on_one_line() {
+        let mut awkward = entry();
+        awkward.path = "weird\tpath\nname".to_string();
+        let line = awkward.to_line();
+        assert_eq!(line.lines().count(), 1);
+
+        let parsed = Entry::parse(line.trim_end()).unwrap();
+        assert_eq!(parsed.path, "weird path name");
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        for line in [
+            "",
+            "garbage",
+            "12\tops\tset\t/dev/hidraw0",
+            "x\ta\tb\tc\td",
+            "1\ta\tb\tc\td\te",
+        ] {
+            assert_eq!(Entry::parse(line), None, "{:?}", line);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use hidapi::HidApi;
+
+use crate::events::escape;
+use crate::qlight::{Light, LightInfo};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+/// Export every connected light and its details for asset tracking
+#[derive(Parser, Debug)]
+pub struct InventoryArgs {
+    /// Output format.
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn json_field(value: Option<&str>) -> String {
+    match value {
+        Some(value) => format!("\"{}\"", escape(value)),
+        None => "null".to_string(),
+    }
+}
+
+fn write_csv(out: &mut impl Write, lights: &[LightInfo]) -> Result<()> {
+    writeln!(out, "path,product,manufacturer,serial,release,interface")?;
+    for info in lights {
+        writeln!(
+            out,
+            "{},{},{},{},{},{}",
+            csv_field(&info.path),
+            csv_field(info.product.as_deref().unwrap_or_default()),
+            csv_field(info.manufacturer.as_deref().unwrap_or_default()),
+            csv_field(info.serial.as_deref().unwrap_or_default()),
+            info.release_string(),
+            info.interface,
+        )?;
+    }
+    Ok(())
+}
+
+fn write_json(out: &mut impl Write, lights: &[LightInfo]) -> Result<()> {
+    writeln!(out, "[")?;
+    for (i, info) in lights.iter().enumerate() {
+        let separator = if i + 1 < lights.len() { "," } else { "" };
+        writeln!(
+            out,
+            "  {{\"path\": {}, \"product\": {}, \"manufacturer\": {}, \"serial\": {}, \"release\": \"{}\", \"interface\": {}}}{}",
+            json_field(Some(&info.path)),
+            json_field(info.product.as_deref()),
+            json_field(info.manufacturer.as_deref()),
+            json_field(info.serial.as_deref()),
+            info.release_string(),
+            info.interface,
+            separator,
+        )?;
+    }
+    writeln!(out, "]")?;
+    Ok(())
+}
+
+pub fn inventory(args: InventoryArgs) -> Result<()> {
+    let hidapi = HidApi::new()?;
+    let lights: Vec<LightInfo> = Light::get_devices(&hidapi).map(LightInfo::from).collect();
+
+    let mut stdout = std::io::stdout().lock();
+    match args.format {
+        Format::Csv => write_csv(&mut stdout, &lights),
+        Format::Json => write_json(&mut stdout, &lights),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn light(path: &str, serial: Option<&str>) -> LightInfo {
+        LightInfo {
+            path: path.to_string(),
+            serial: serial.map(String::from),
+            manufacturer: Some("Qlight".to_string()),
+            product: Some("ST45L".to_string()),
+            release: 0x0102,
+            interface: 0,
+        }
+    }
+
+    fn csv(lights: &[LightInfo]) -> String {
+        let mut out = Vec::new();
+        write_csv(&mut out, lights).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn json(lights: &[LightInfo]) -> String {
+        let mut out = Vec::new();
+        write_json(&mut out, lights).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn quotes_csv_fields_only_when_needed() {
+        let cases = [
+            ("plain", "plain"),
+            ("", ""),
+            ("a,b", "\"a,b\""),
+            ("say \"hi\"", "\"say \"\"hi\"\"\""),
+            ("two\nlines", "\"two\nlines\""),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(csv_field(value), expected, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn writes_csv() {
+        let lights = [light("1-1:1.0", Some("A,1")), light("1-2:1.0", None)];
+        assert_eq!(
+            csv(&lights),
+            "path,product,manufacturer,serial,release,interface\n\
+             1-1:1.0,ST45L,Qlight,\"A,1\",1.02,0\n\
+             1-2:1.0,ST45L,Qlight,,1.02,0\n"
+        );
+    }
+
+    #[test]
+    fn writes_null_for_missing_json_fields() {
+        let mut info = light("1-1:1.0", None);
+        info.product = None;
+        assert_eq!(
+            json(&[info]),
+            "[\n  {\"path\": \"1-1:1.0\", \"product\": null, \"manufacturer\": \"Qlight\", \
+             \"serial\": null, \"release\": \"1.02\", \"interface\": 0}\n]\n"
+        );
+    }
+
+    #[test]
+    fn separates_json_entries() {
+        assert_eq!(json(&[]), "[\n]\n");
+
+        let one = json(&[light("a", Some("1"))]);
+        assert_eq!(one.lines().count(), 3);
+        assert!(one.lines().nth(1).unwrap().ends_with('}'));
+
+        let two = json(&[light("a", Some("1")), light("b", Some("2"))]);
+        let lines: Vec<&str> = two.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].ends_with("},"));
+        assert!(lines[2].ends_with('}'));
+        assert_eq!(lines[3], "]");
+    }
+}
@@ -0,0 +1,277 @@
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+
+use crate::exec::parse_line;
+use crate::qlight::LightCommandSet;
+use crate::verbosity::detail;
+use crate::{update_all, Picker};
+
+/// `EV_KEY` from linux/input-event-codes.h.
+const EV_KEY: u16 = 0x01;
+
+/// Key values: 0 is a release, 1 a press and 2 autorepeat.
+const KEY_PRESSED: i32 = 1;
+
+/// `EVIOCGRAB`, `_IOW('E', 0x90, int)` from linux/input.h.
+const EVIOCGRAB: u32 = 0x4004_4590;
+
+/// Names for the keys footswitches, macro pads and presenter remotes
+/// usually send, from linux/input-event-codes.h. Anything else can be given
+/// by its number, which `evtest` shows.
+const KEYS: &[(&str, u16)] = &[
+    ("ESC", 1),
+    ("1", 2),
+    ("2", 3),
+    ("3", 4),
+    ("4", 5),
+    ("5", 6),
+    ("6", 7),
+    ("7", 8),
+    ("8", 9),
+    ("9", 10),
+    ("0", 11),
+    ("TAB", 15),
+    ("Q", 16),
+    ("W", 17),
+    ("E", 18),
+    ("R", 19),
+    ("T", 20),
+    ("Y", 21),
+    ("U", 22),
+    ("I", 23),
+    ("O", 24),
+    ("P", 25),
+    ("ENTER", 28),
+    ("A", 30),
+    ("S", 31),
+    ("D", 32),
+    ("F", 33),
+    ("G", 34),
+    ("H", 35),
+    ("J", 36),
+    ("K", 37),
+    ("L", 38),
+    ("Z", 44),
+    ("X", 45),
+    ("C", 46),
+    ("V", 47),
+    ("B", 48),
+    ("N", 49),
+    ("M", 50),
+    ("SPACE", 57),
+    ("F1", 59),
+    ("F2", 60),
+    ("F3", 61),
+    ("F4", 62),
+    ("F5", 63),
+    ("F6", 64),
+    ("F7", 65),
+    ("F8", 66),
+    ("F9", 67),
+    ("F10", 68),
+    ("F11", 87),
+    ("F12", 88),
+    ("UP", 103),
+    ("PAGEUP", 104),
+    ("LEFT", 105),
+    ("RIGHT", 106),
+    ("DOWN", 108),
+    ("PAGEDOWN", 109),
+    ("NEXTSONG", 163),
+    ("PLAYPAUSE", 164),
+    ("PREVIOUSSONG", 165),
+    ("F13", 183),
+    ("F14", 184),
+    ("F15", 185),
+    ("F16", 186),
+    ("F17", 187),
+    ("F18", 188),
+    ("F19", 189),
+    ("F20", 190),
+    ("F21", 191),
+    ("F22", 192),
+    ("F23", 193),
+    ("F24", 194),
+];
+
+/// Drive the lights from key presses on an input device (Linux only)
+///
+/// Reads a keyboard, footswitch or macro pad through evdev and applies
+/// commands when bound keys are pressed, e.g. `qlight keys --all --grab
+/// --device /dev/input/by-id/usb-footswitch-event-kbd --bind 'B=reset
+/// red:on|reset'` makes the pedal toggle an on-air light. Reading input
+/// devices usually needs root or membership of the `input` group.
+#[derive(Parser, Debug)]
+pub struct KeysArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// The input device to read, preferably a stable /dev/input/by-id path.
+    #[arg(long)]
+    device: PathBuf,
+
+    /// Bind a key to commands, as `KEY=COMMANDS`. COMMANDS is a line in the
+    /// same format as `exec` reads, like `reset red:on`. Separate several
+    /// with `|` to step through them on each press. KEY is a name like `A`,
+    /// `SPACE`, `PAGEDOWN` or `F13`, with or without a `KEY_` prefix, or a
+    /// key code.
+    #[arg(long, value_name = "KEY=COMMANDS", required = true, value_parser = parse_binding)]
+    bind: Vec<Binding>,
+
+    /// Keep the device's key presses from reaching anything else while
+    /// this runs.
+    #[arg(long)]
+    grab: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Binding {
+    code: u16,
+    lightsets: Vec<LightCommandSet>,
+}
+
+/// Names win over codes, so `1` is the 1 key rather than code 1.
+fn key_code(name: &str) -> Result<u16> {
+    let upper = name.to_ascii_uppercase();
+    let upper = upper.strip_prefix("KEY_").unwrap_or(&upper);
+    if let Some((_, code)) = KEYS.iter().find(|(key, _)| *key == upper) {
+        return Ok(*code);
+    }
+    name.parse()
+        .map_err(|_| anyhow!("Unknown key {}, give its key code instead", name))
+}
+
+fn parse_binding(s: &str) -> Result<Binding> {
+    let Some((key, commands)) = s.split_once('=') else {
+        bail!("Expected KEY=COMMANDS, got {}", s);
+    };
+    Ok(Binding {
+        code: key_code(key.trim())?,
+        lightsets: commands.split('|').map(parse_line).collect::<Result<_>>()?,
+    })
+}
+
+/// Pulls the type, code and value out of a `struct input_event`, which
+/// starts with a `struct timeval` the size of the platform's `long`s.
+fn parse_event(event: &[u8]) -> (u16, u16, i32) {
+    let fields = &event[std::mem::size_of::<libc::timeval>()..];
+    (
+        u16::from_ne_bytes([fields[0], fields[1]]),
+        u16::from_ne_bytes([fields[2], fields[3]]),
+        i32::from_ne_bytes([fields[4], fields[5], fields[6], fields[7]]),
+    )
+}
+
+fn grab(device: &File) -> std::io::Result<()> {
+    // SAFETY: EVIOCGRAB takes an int by value and the fd is open for as
+    // long as `device` is borrowed.
+    if unsafe { libc::ioctl(device.as_raw_fd(), EVIOCGRAB as _, 1) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub fn keys(args: KeysArgs) -> Result<()> {
+    let bindings = args.bind;
+    for (i, binding) in bindings.iter().enumerate() {
+        if bindings[..i].iter().any(|b| b.code == binding.code) {
+            crate::Args::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!("Key code {} is bound more than once", binding.code),
+                )
+                .exit();
+        }
+    }
+    let mut presses = vec![0; bindings.len()];
+
+    let mut lights = args.picker.open()?;
+
+    let mut device = File::open(&args.device)
+        .with_context(|| format!("Failed to open {}", args.device.display()))?;
+    if args.grab {
+        grab(&device).with_context(|| format!("Failed to grab {}", args.device.display()))?;
+    }
+
+    let mut event = [0; std::mem::size_of::<libc::input_event>()];
+    loop {
+        match device.read_exact(&mut event) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                bail!("{} went away", args.device.display())
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read {}", args.device.display()))
+            }
+        }
+
+        let (kind, code, value) = parse_event(&event);
+        if kind != EV_KEY || value != KEY_PRESSED {
+            continue;
+        }
+        let Some(i) = bindings.iter().position(|b| b.code == code) else {
+            detail!("Key {} isn't bound", code);
+            continue;
+        };
+
+        let binding = &bindings[i];
+        let lightset = &binding.lightsets[presses[i] % binding.lightsets.len()];
+        presses[i] += 1;
+        detail!("Key {} pressed", code);
+        update_all(&mut lights, lightset)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::qlight::LightMode;
+
+    #[test]
+    fn looks_up_key_codes() {
+        let cases = [
+            ("A", 30),
+            ("a", 30),
+            ("KEY_A", 30),
+            ("key_pagedown", 109),
+            ("F13", 183),
+            ("1", 2),
+            ("0", 11),
+            ("272", 272),
+        ];
+        for (name, code) in cases {
+            assert_eq!(key_code(name).unwrap(), code, "{}", name);
+        }
+        assert!(key_code("KEY_NOPE").is_err());
+        assert!(key_code("70000").is_err());
+    }
+
+    #[test]
+    fn parses_bindings() {
+        let binding = parse_binding("B=reset red:on|reset").unwrap();
+        assert_eq!(binding.code, 48);
+        assert_eq!(binding.lightsets.len(), 2);
+        assert_eq!(binding.lightsets[0].red, LightMode::On);
+        assert_eq!(binding.lightsets[1].red, LightMode::Off);
+
+        for bad in ["B", "B=purple:on", "NOPE=red:on", "B=red:on|red:dim"] {
+            assert!(parse_binding(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn reads_input_events() {
+        let mut event = vec![0xaa; std::mem::size_of::<libc::timeval>()];
+        event.extend(EV_KEY.to_ne_bytes());
+        event.extend(30u16.to_ne_bytes());
+        event.extend(KEY_PRESSED.to_ne_bytes());
+        assert_eq!(event.len(), std::mem::size_of::<libc::input_event>());
+        assert_eq!(parse_event(&event), (EV_KEY, 30, KEY_PRESSED));
+    }
+}
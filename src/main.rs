@@ -1,15 +1,59 @@
 use std::io::Write;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::Duration;
 
 use clap::{ArgGroup, Parser};
 use hidapi::HidApi;
-use qlight::{Color, Light, LightCommand, LightMode, LightCommandSet};
+use qlight::{Color, Light, LightCommand, LightCommandSet, LightInfo, LightMode};
 
 use anyhow::{bail, Result};
+use exit::{Failure, Kind};
+use verbosity::detail;
 
+mod blink;
+mod countdown;
+mod events;
+mod exec;
+mod exit;
+#[cfg(all(target_os = "linux", feature = "gpio"))]
+mod gpio;
+mod history;
+mod inventory;
+#[cfg(target_os = "linux")]
+mod keys;
+mod netmon;
+mod osc;
+mod protocol;
 mod qlight;
+mod state;
+mod stats;
+mod testwatch;
+mod threshold;
+mod ups;
+mod verbosity;
+mod vu;
 
 #[derive(Parser, Debug)]
 struct Args {
+    /// Print a JSON line on stdout for every state written and every error.
+    #[arg(long, global = true)]
+    events: bool,
+
+    /// Send an OSC message over UDP to HOST:PORT for every state written
+    /// and every error. Can be given more than once.
+    #[arg(long, global = true, value_name = "HOST:PORT", value_parser = osc::parse_destination)]
+    osc: Vec<SocketAddr>,
+
+    /// Only print errors and warnings.
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print more detail, e.g. every light opened and written to. For
+    /// `list`, also show device details.
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
     #[command(subcommand)]
     action: Action,
 }
@@ -19,16 +63,56 @@ enum Action {
     Set(SetArgs),
     /// List all lights connected to this system
     List,
+    Blackout(BlackoutArgs),
+    Countdown(countdown::CountdownArgs),
+    Threshold(threshold::ThresholdArgs),
+    Netmon(netmon::NetmonArgs),
+    Ups(ups::UpsArgs),
+    Exec(exec::ExecArgs),
+    Inventory(inventory::InventoryArgs),
+    TestWatch(testwatch::TestWatchArgs),
+    History(history::HistoryArgs),
+    Stats(stats::StatsArgs),
+    Vu(vu::VuArgs),
+    #[cfg(target_os = "linux")]
+    Keys(keys::KeysArgs),
+    #[cfg(all(target_os = "linux", feature = "gpio"))]
+    Gpio(gpio::GpioArgs),
 }
 
-/// Set the light to a specific set of colors
-#[derive(Parser, Debug)]
+impl Action {
+    /// The subcommand's name as typed, for history and stats.
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Set(_) => "set",
+            Action::List => "list",
+            Action::Blackout(_) => "blackout",
+            Action::Countdown(_) => "countdown",
+            Action::Threshold(_) => "threshold",
+            Action::Netmon(_) => "netmon",
+            Action::Ups(_) => "ups",
+            Action::Exec(_) => "exec",
+            Action::Inventory(_) => "inventory",
+            Action::TestWatch(_) => "test-watch",
+            Action::History(_) => "history",
+            Action::Stats(_) => "stats",
+            Action::Vu(_) => "vu",
+            #[cfg(target_os = "linux")]
+            Action::Keys(_) => "keys",
+            #[cfg(all(target_os = "linux", feature = "gpio"))]
+            Action::Gpio(_) => "gpio",
+        }
+    }
+}
+
+/// Which lights a command applies to, and how long to wait on them
+#[derive(clap::Args, Debug)]
 #[clap(group(
     ArgGroup::new("picker")
         .required(true)
         .args(&["all", "path"])
 ))]
-struct SetArgs {
+struct Picker {
     /// Apply the commands to a specific lights. Use `list` to get the paths.
     #[clap(long, value_name = "PATH")]
     path: Option<String>,
@@ -37,12 +121,103 @@ struct SetArgs {
     #[clap(long)]
     all: bool,
 
+    /// Give up on a light if opening or writing to it takes longer than
+    /// this, e.g. 500ms.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_duration)]
+    timeout: Option<Duration>,
+}
+
+impl Picker {
+    fn open(&self) -> Result<Lights> {
+        Lights::open(self.path.as_deref(), self.timeout)
+    }
+}
+
+/// The lights a command picked. Any that couldn't be opened have already
+/// been reported, but still count against the command's result.
+struct Lights {
+    opened: Vec<Light>,
+    unopened: usize,
+    /// Whether writes go in the history. Commands that rewrite the same
+    /// state over and over turn this off after the first write.
+    recording: bool,
+}
+
+impl Lights {
+    /// Opens every light, or just the one at `path`, carrying on past any
+    /// that fail so one bad device can't stop the rest.
+    fn open(path: Option<&str>, timeout: Option<Duration>) -> Result<Self> {
+        let hidapi = hidapi()?;
+        let mut opened = Vec::new();
+        let mut failures = Vec::new();
+        for device in Light::get_devices(hidapi) {
+            if let Some(path) = path {
+                if device.path().to_bytes() != path.as_bytes() {
+                    continue;
+                }
+            }
+            let device_path = device.path().to_string_lossy();
+            match Light::open(hidapi, device, timeout) {
+                Ok(light) => {
+                    detail!("Opened {}", device_path);
+                    opened.push(light);
+                }
+                Err(e) => {
+                    let error = exit::open_error(&device_path, e);
+                    failures.push((device_path.into_owned(), error));
+                }
+            }
+        }
+
+        if opened.is_empty() && failures.is_empty() {
+            let message = match path {
+                Some(path) => format!("No light found at {}", path),
+                None => "No lights found".to_string(),
+            };
+            bail!(Failure::new(Kind::NoDevice, message));
+        }
+        if opened.is_empty() {
+            return Err(combine_failures(failures, 0, 0));
+        }
+
+        for (path, e) in &failures {
+            report(path, e);
+        }
+        Ok(Self {
+            opened,
+            unopened: failures.len(),
+            recording: true,
+        })
+    }
+}
+
+/// Turn off every connected light and silence its buzzer
+#[derive(Parser, Debug)]
+struct BlackoutArgs {
+    /// Give up on a light if opening or writing to it takes longer than
+    /// this, so one wedged light can't hold up the rest.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_duration, default_value = "2s")]
+    timeout: Duration,
+}
+
+/// Set the light to a specific set of colors
+#[derive(Parser, Debug)]
+struct SetArgs {
+    #[command(flatten)]
+    picker: Picker,
+
     /// If set, any unspecified color will be turned off.
     #[clap(long)]
     reset: bool,
 
+    /// Blink from software with this period, keeping all the lights (and
+    /// other qlight processes) flashing in unison. Keeps running until
+    /// interrupted.
+    #[arg(long, value_name = "PERIOD", value_parser = parse_duration)]
+    sync_blink: Option<Duration>,
+
     /// A list of [color]:[state]
-    /// 
+    ///
     /// Valid colors: red, yellow, green, blue, white
     ///
     /// Valid states: off, on, blink
@@ -52,16 +227,172 @@ struct SetArgs {
 
 fn parse_command(s: &str) -> Result<LightCommand> {
     let Some((color, mode_name)) = s.split_once(':') else {
-        bail!("Expected format of [red,yellow,green,blue,white]:[on,off,blink] got {}", s);
+        bail!(
+            "Expected format of [red,yellow,green,blue,white]:[on,off,blink] got {}",
+            s
+        );
     };
 
-    let color = Color::try_from(color)?; 
+    let color = Color::try_from(color)?;
     let light_mode = LightMode::try_from(mode_name)?;
 
     Ok((color, light_mode))
 }
 
-fn list(_args: Args) -> Result<()> {
+fn parse_duration(s: &str) -> Result<Duration> {
+    if s.is_empty() {
+        bail!("Expected a duration like 90s, 10m or 1h30m, got nothing");
+    }
+
+    let mut millis = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let number_end = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(number_end);
+        let unit_end = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+
+        let Ok(number) = number.parse::<u64>() else {
+            bail!("Expected a duration like 90s, 10m or 1h30m, got {}", s);
+        };
+        let scale = match unit {
+            "h" => 3_600_000,
+            "m" => 60_000,
+            "s" | "" => 1_000,
+            "ms" => 1,
+            _ => bail!("Expected a duration unit of [h, m, s, ms], got {}", unit),
+        };
+
+        let Some(total) = number
+            .checked_mul(scale)
+            .and_then(|n| n.checked_add(millis))
+        else {
+            bail!("Expected a duration like 90s, 10m or 1h30m, got {}", s);
+        };
+        millis = total;
+        rest = tail;
+    }
+
+    Ok(Duration::from_millis(millis))
+}
+
+/// For timeouts and intervals, where 0 would time out every light or spin.
+fn parse_nonzero_duration(s: &str) -> Result<Duration> {
+    let duration = parse_duration(s)?;
+    if duration.is_zero() {
+        bail!("Expected a duration above 0, got {}", s);
+    }
+    Ok(duration)
+}
+
+fn update(light: &mut Light, lightset: &LightCommandSet) -> Result<()> {
+    match light.update(lightset) {
+        Ok(_) => {
+            detail!("{}: {}", light.info().path, lightset);
+            events::state(&light.info().path, lightset);
+            osc::state(&light.info().path, lightset);
+            Ok(())
+        }
+        Err(e) => {
+            Err(anyhow::Error::from(e).context(format!("Failed to write to {}", light.info().path)))
+        }
+    }
+}
+
+/// Keeps a state written to a light in the history and stats.
+fn record(path: &str, lightset: &LightCommandSet) {
+    history::record(path, lightset);
+    stats::record(lightset);
+}
+
+/// Reports a light's failure that isn't going to be returned, such as one
+/// of several in `blackout`. Whatever does get returned is reported by
+/// `main`, so every failure shows up exactly once.
+fn report(path: &str, error: &anyhow::Error) {
+    eprintln!("{:#}", error);
+    events::error(Some(path), &format_args!("{:#}", error));
+    osc::error(Some(path), &format_args!("{:#}", error));
+}
+
+/// The hidapi context for opening lights. It lives for the rest of the
+/// process, since opens with a timeout borrow it from a thread that may
+/// outlive the call.
+fn hidapi() -> Result<&'static HidApi> {
+    Ok(Box::leak(Box::new(HidApi::new()?)))
+}
+
+/// Turns the failures of a command that carried on across lights into its
+/// error, after reporting any that it doesn't return. When nothing worked
+/// the first failure is returned as is, so its exit code still says why.
+/// Otherwise it's a partial failure, counting `earlier` failures that were
+/// already reported.
+fn combine_failures(
+    mut failures: Vec<(String, anyhow::Error)>,
+    earlier: usize,
+    succeeded: usize,
+) -> anyhow::Error {
+    if succeeded == 0 && !failures.is_empty() {
+        let (_, first) = failures.remove(0);
+        for (path, e) in &failures {
+            report(path, e);
+        }
+        return first;
+    }
+
+    for (path, e) in &failures {
+        report(path, e);
+    }
+    let failed = earlier + failures.len();
+    let message = format!(
+        "Failed to update {} of {} lights",
+        failed,
+        failed + succeeded
+    );
+    Failure::new(Kind::PartialFailure, message).into()
+}
+
+/// Writes to every light, carrying on past any that fail, and returns the
+/// failures.
+fn write_all(lights: &mut Lights, lightset: &LightCommandSet) -> Vec<(String, anyhow::Error)> {
+    let mut failures = Vec::new();
+    for light in &mut lights.opened {
+        match update(light, lightset) {
+            Ok(()) if lights.recording => record(&light.info().path, lightset),
+            Ok(()) => {}
+            Err(e) => failures.push((light.info().path.clone(), e)),
+        }
+    }
+    failures
+}
+
+/// Writes to every light, failing if any of these writes did. Lights that
+/// couldn't be opened were reported then and don't count again, so a
+/// watcher keeps going with the lights it has.
+fn update_all(lights: &mut Lights, lightset: &LightCommandSet) -> Result<()> {
+    let failures = write_all(lights, lightset);
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let succeeded = lights.opened.len() - failures.len();
+    Err(combine_failures(failures, 0, succeeded))
+}
+
+/// Like [`update_all`] for commands that write once and exit, where lights
+/// that couldn't be opened also make it a partial failure.
+fn update_once(lights: &mut Lights, lightset: &LightCommandSet) -> Result<()> {
+    let failures = write_all(lights, lightset);
+    if failures.is_empty() && lights.unopened == 0 {
+        return Ok(());
+    }
+    let succeeded = lights.opened.len() - failures.len();
+    Err(combine_failures(failures, lights.unopened, succeeded))
+}
+
+fn list() -> Result<()> {
     let hidapi = HidApi::new()?;
     let devices = Light::get_devices(&hidapi);
 
@@ -70,6 +401,28 @@ fn list(_args: Args) -> Result<()> {
     for device in devices {
         stdout.write_all(device.path().to_bytes())?;
         writeln!(stdout)?;
+
+        if verbosity::get() == verbosity::Level::Verbose {
+            let info = LightInfo::from(device);
+            let unknown = "(unknown)";
+            writeln!(
+                stdout,
+                "    product:      {}",
+                info.product.as_deref().unwrap_or(unknown)
+            )?;
+            writeln!(
+                stdout,
+                "    manufacturer: {}",
+                info.manufacturer.as_deref().unwrap_or(unknown)
+            )?;
+            writeln!(
+                stdout,
+                "    serial:       {}",
+                info.serial.as_deref().unwrap_or("(none)")
+            )?;
+            writeln!(stdout, "    release:      {}", info.release_string())?;
+            writeln!(stdout, "    interface:    {}", info.interface)?;
+        }
     }
     Ok(())
 }
@@ -85,19 +438,134 @@ fn set(args: SetArgs) -> Result<()> {
         lightset.set(*color, *lightmode);
     }
 
-    let hidapi = HidApi::new()?;
-    for light in Light::get_devices(&hidapi) {
-        let light = Light::new(light.open_device(&hidapi)?);
-        light.update(&lightset)?;
+    let mut lights = args.picker.open()?;
+
+    match args.sync_blink {
+        Some(period) => blink::sync_blink(&mut lights, &lightset, period),
+        None => update_once(&mut lights, &lightset),
     }
+}
 
-    Ok(())
+fn blackout(args: BlackoutArgs) -> Result<()> {
+    let mut lights = Lights::open(None, Some(args.timeout))?;
+    update_once(&mut lights, &LightCommandSet::default_off())
 }
 
-fn main() -> Result<()> {
+fn main() -> ExitCode {
     let cli = Args::parse();
-    match cli.action {
+    if cli.events {
+        events::enable();
+    }
+    if cli.quiet {
+        verbosity::set(verbosity::Level::Quiet);
+    } else if cli.verbose {
+        verbosity::set(verbosity::Level::Verbose);
+    }
+
+    state::set_command(cli.action.name());
+    stats::start();
+
+    let result = osc::enable(&cli.osc).and_then(|()| match cli.action {
         Action::Set(s) => set(s),
-        Action::List => list(cli),
+        Action::List => list(),
+        Action::Blackout(b) => blackout(b),
+        Action::Countdown(c) => countdown::countdown(c),
+        Action::Threshold(t) => threshold::threshold(t),
+        Action::Netmon(n) => netmon::netmon(n),
+        Action::Ups(u) => ups::ups(u),
+        Action::Exec(e) => exec::exec(e),
+        Action::Inventory(i) => inventory::inventory(i),
+        Action::TestWatch(t) => testwatch::test_watch(t),
+        Action::History(h) => history::history(h),
+        Action::Stats(s) => stats::stats(s),
+        Action::Vu(v) => vu::vu(v),
+        #[cfg(target_os = "linux")]
+        Action::Keys(k) => keys::keys(k),
+        #[cfg(all(target_os = "linux", feature = "gpio"))]
+        Action::Gpio(g) => gpio::gpio(g),
+    });
+    stats::finish(result.is_err());
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            events::error(None, &format_args!("{:#}", e));
+            osc::error(None, &format_args!("{:#}", e));
+            eprintln!("Error: {:?}", e);
+            exit::code(&e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unopened_lights_only_fail_one_shot_writes() {
+        let mut lights = Lights {
+            opened: Vec::new(),
+            unopened: 2,
+            recording: false,
+        };
+        let lightset = LightCommandSet::default_off();
+
+        // Watchers write again and again, and must not stop on the first.
+        for _ in 0..3 {
+            assert!(update_all(&mut lights, &lightset).is_ok());
+        }
+
+        let error = update_once(&mut lights, &lightset).unwrap_err();
+        assert_eq!(
+            exit::code(&error),
+            ExitCode::from(Kind::PartialFailure as u8)
+        );
+        assert_eq!(error.to_string(), "Failed to update 2 of 2 lights");
+    }
+
+    #[test]
+    fn parse_duration_accepts_units() {
+        let cases = [
+            ("90s", 90_000),
+            ("1h30m", 5_400_000),
+            ("500ms", 500),
+            ("10", 10_000),
+            ("2m500ms", 120_500),
+        ];
+        for (input, millis) in cases {
+            assert_eq!(
+                parse_duration(input).unwrap(),
+                Duration::from_millis(millis),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parse_duration_rejects_garbage() {
+        for input in [
+            "",
+            "1.5s",
+            "m",
+            "10x",
+            "18446744073709551615h",
+            "5124095576030432h",
+        ] {
+            assert!(parse_duration(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_nonzero_duration_rejects_zero() {
+        assert_eq!(
+            parse_nonzero_duration("1ms").unwrap(),
+            Duration::from_millis(1)
+        );
+        for input in ["0", "0s", "0h0m", "nope"] {
+            assert!(parse_nonzero_duration(input).is_err(), "{}", input);
+        }
+        assert!(Args::try_parse_from(["qlight", "blackout", "--timeout", "0"]).is_err());
+        assert!(Args::try_parse_from(["qlight", "set", "--all", "--timeout", "0s"]).is_err());
     }
 }
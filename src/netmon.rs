@@ -0,0 +1,269 @@
+use std::process::Command;
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use crate::threshold::{Level, Thresholds};
+use crate::verbosity::status;
+use crate::{parse_duration, parse_nonzero_duration, update_all, Picker};
+
+/// Ping hosts and show green, yellow or red based on packet loss and latency
+#[derive(Parser, Debug)]
+pub struct NetmonArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// Host to ping, or `gateway` for the default gateway. Can be given
+    /// more than once, the worst target wins.
+    #[arg(long = "target", value_name = "HOST", required = true)]
+    targets: Vec<String>,
+
+    /// Packet loss percentage to show yellow at.
+    #[arg(long, value_name = "PERCENT", default_value_t = 10.0)]
+    warn_loss: f64,
+
+    /// Packet loss percentage to show red at.
+    #[arg(long, value_name = "PERCENT", default_value_t = 50.0)]
+    crit_loss: f64,
+
+    /// Average round trip time to show yellow at.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "100ms")]
+    warn_latency: Duration,
+
+    /// Average round trip time to show red at.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "500ms")]
+    crit_latency: Duration,
+
+    /// Pings to send to each target per check.
+    #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+
+    /// Time to wait between checks.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_duration, default_value = "10s")]
+    interval: Duration,
+
+    /// Blink red instead of holding it.
+    #[arg(long)]
+    blink: bool,
+
+    /// Check once and exit instead of watching.
+    #[arg(long)]
+    once: bool,
+}
+
+#[derive(Debug, Default)]
+struct PingSummary {
+    loss_percent: f64,
+    average_ms: Option<f64>,
+}
+
+/// Pulls packet loss and average latency out of the summary printed by the
+/// system `ping`, which looks different on Linux, macOS and Windows.
+fn parse_ping_output(output: &str) -> Option<PingSummary> {
+    let mut summary = PingSummary::default();
+    let mut found_loss = false;
+
+    for line in output.lines() {
+        if line.contains("loss") {
+            let percent = line.split_whitespace().find(|word| word.contains('%'))?;
+            let percent = percent.trim_matches(|c: char| !c.is_ascii_digit() && c != '.');
+            summary.loss_percent = percent.parse().ok()?;
+            found_loss = true;
+        } else if line.contains("min/avg/max") {
+            // rtt min/avg/max/mdev = 10.1/11.2/12.3/0.5 ms
+            let (_, values) = line.split_once('=')?;
+            summary.average_ms = values.trim().split('/').nth(1)?.parse().ok();
+        } else if let Some((_, average)) = line.split_once("Average = ") {
+            summary.average_ms = average.trim().trim_end_matches("ms").parse().ok();
+        }
+    }
+
+    found_loss.then_some(summary)
+}
+
+fn ping(host: &str, count: u32) -> Result<PingSummary> {
+    let count_flag = if cfg!(windows) { "-n" } else { "-c" };
+    let output = Command::new("ping")
+        .arg(count_flag)
+        .arg(count.to_string())
+        .arg(host)
+        .output()
+        .context("Failed to run ping")?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match parse_ping_output(&stdout) {
+        Some(summary) => Ok(summary),
+        // Unknown hosts and the like never get as far as a summary.
+        None if !output.status.success() => Ok(PingSummary {
+            loss_percent: 100.0,
+            average_ms: None,
+        }),
+        None => bail!("Couldn't understand ping output for {}", host),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Result<String> {
+    let routes = std::fs::read_to_string("/proc/net/route")?;
+
+    // Iface Destination Gateway ... with addresses in little endian hex.
+    for line in routes.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() > 2 && fields[1] == "00000000" {
+            let gateway = u32::from_str_radix(fields[2], 16)?;
+            return Ok(std::net::Ipv4Addr::from(gateway.swap_bytes()).to_string());
+        }
+    }
+
+    bail!("No default gateway found in /proc/net/route")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Result<String> {
+    bail!("The `gateway` target is only supported on Linux, pass its address instead")
+}
+
+pub fn netmon(args: NetmonArgs) -> Result<()> {
+    let loss = Thresholds {
+        warn: args.warn_loss,
+        crit: args.crit_loss,
+    };
+    let latency = Thresholds {
+        warn: args.warn_latency.as_secs_f64() * 1000.0,
+        crit: args.crit_latency.as_secs_f64() * 1000.0,
+    };
+
+    let mut targets = Vec::new();
+    for target in &args.targets {
+        if target == "gateway" {
+            targets.push(default_gateway()?);
+        } else {
+            targets.push(target.clone());
+        }
+    }
+
+    let mut lights = args.picker.open()?;
+
+    let mut shown = None;
+    loop {
+        let mut level = Level::Normal;
+        for target in &targets {
+            let summary = ping(target, args.count)?;
+            match summary.average_ms {
+                Some(average) => status!(
+                    "{}: {}% loss, {:.1}ms",
+                    target,
+                    summary.loss_percent,
+                    average
+                ),
+                None => status!("{}: {}% loss", target, summary.loss_percent),
+            }
+
+            level = level.max(loss.level(summary.loss_percent));
+            if let Some(average) = summary.average_ms {
+                level = level.max(latency.level(average));
+            }
+        }
+
+        if shown != Some(level) {
+            update_all(&mut lights, &level.lightset(args.blink))?;
+            shown = Some(level);
+        }
+
+        if args.once {
+            return Ok(());
+        }
+        sleep(args.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINUX: &str = "\
+PING example.com (93.184.216.34) 56(84) bytes of data.
+64 bytes from 93.184.216.34: icmp_seq=1 ttl=56 time=11.2 ms
+
+--- example.com ping statistics ---
+5 packets transmitted, 4 received, 20% packet loss, time 4005ms
+rtt min/avg/max/mdev = 10.123/11.234/12.345/0.456 ms
+";
+
+    const LINUX_ERRORS: &str = "\
+PING 10.0.0.99 (10.0.0.99) 56(84) bytes of data.
+From 10.0.0.1 icmp_seq=1 Destination Host Unreachable
+
+--- 10.0.0.99 ping statistics ---
+5 packets transmitted, 0 received, +5 errors, 100% packet loss, time 4082ms, pipe 4
+";
+
+    const MACOS: &str = "\
+PING example.com (93.184.216.34): 56 data bytes
+64 bytes from 93.184.216.34: icmp_seq=0 ttl=56 time=11.2 ms
+
+--- example.com ping statistics ---
+5 packets transmitted, 5 packets received, 0.0% packet loss
+round-trip min/avg/max/stddev = 10.123/11.234/12.345/0.456 ms
+";
+
+    const WINDOWS: &str = "\
+Pinging example.com [93.184.216.34] with 32 bytes of data:
+Reply from 93.184.216.34: bytes=32 time=11ms TTL=56
+
+Ping statistics for 93.184.216.34:
+    Packets: Sent = 4, Received = 3, Lost = 1 (25% loss),
+Approximate round trip times in milli-seconds:
+    Minimum = 10ms, Maximum = 12ms, Average = 11ms
+";
+
+    #[test]
+    fn parses_linux_summary() {
+        let summary = parse_ping_output(LINUX).unwrap();
+        assert_eq!(summary.loss_percent, 20.0);
+        assert_eq!(summary.average_ms, Some(11.234));
+    }
+
+    #[test]
+    fn parses_total_loss_with_errors() {
+        let summary = parse_ping_output(LINUX_ERRORS).unwrap();
+        assert_eq!(summary.loss_percent, 100.0);
+        assert_eq!(summary.average_ms, None);
+    }
+
+    #[test]
+    fn parses_macos_summary() {
+        let summary = parse_ping_output(MACOS).unwrap();
+        assert_eq!(summary.loss_percent, 0.0);
+        assert_eq!(summary.average_ms, Some(11.234));
+    }
+
+    #[test]
+    fn parses_windows_summary() {
+        let summary = parse_ping_output(WINDOWS).unwrap();
+        assert_eq!(summary.loss_percent, 25.0);
+        assert_eq!(summary.average_ms, Some(11.0));
+    }
+
+    #[test]
+    fn unknown_host_has_no_summary() {
+        let outputs = [
+            "",
+            "ping: nosuchhost: Name or service not known\n",
+            "Ping request could not find host nosuchhost. Please check the name and try again.\r\n",
+        ];
+        for output in outputs {
+            assert!(parse_ping_output(output).is_none(), "{:?}", output);
+        }
+    }
+
+    #[test]
+    fn rejects_zero_count() {
+        let argv = ["netmon", "--all", "--target", "gateway", "--count", "0"];
+        assert!(NetmonArgs::try_parse_from(argv).is_err());
+        let argv = ["netmon", "--all", "--target", "gateway", "--count", "1"];
+        assert!(NetmonArgs::try_parse_from(argv).is_ok());
+    }
+}
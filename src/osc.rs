@@ -0,0 +1,137 @@
+//! Sends what the CLI does as OSC messages over UDP, so show control
+//! software like QLab or TouchDesigner can react to the lights. Off unless
+//! `--osc HOST:PORT` is passed, which can be given more than once. Every
+//! argument is a string:
+//!
+//! ```text
+//! /qlight/state <path> <red> <yellow> <green> <blue> <white> <sound>
+//! /qlight/error <path, or "" when it isn't about one light> <message>
+//! ```
+
+use std::fmt::Display;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+
+use crate::qlight::LightCommandSet;
+
+static SOCKETS: OnceLock<Vec<UdpSocket>> = OnceLock::new();
+
+/// Resolves a `HOST:PORT` destination for `--osc`.
+pub fn parse_destination(s: &str) -> Result<SocketAddr> {
+    s.to_socket_addrs()
+        .with_context(|| format!("Expected HOST:PORT, got {}", s))?
+        .next()
+        .with_context(|| format!("{} didn't resolve to any address", s))
+}
+
+pub fn enable(destinations: &[SocketAddr]) -> Result<()> {
+    let sockets = destinations
+        .iter()
+        .map(|destination| {
+            let local: SocketAddr = if destination.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0; 16], 0).into()
+            };
+            let socket = UdpSocket::bind(local)?;
+            socket.connect(destination)?;
+            Ok(socket)
+        })
+        .collect::<std::io::Result<_>>()
+        .context("Failed to set up OSC")?;
+    let _ = SOCKETS.set(sockets);
+    Ok(())
+}
+
+/// Appends `s` as an OSC string: null terminated, padded to four bytes.
+fn push_string(message: &mut Vec<u8>, s: &str) {
+    message.extend_from_slice(s.as_bytes());
+    message.extend(std::iter::repeat_n(0, 4 - s.len() % 4));
+}
+
+/// Encodes an OSC message whose arguments are all strings.
+fn encode(address: &str, args: &[&str]) -> Vec<u8> {
+    let mut message = Vec::new();
+    push_string(&mut message, address);
+    push_string(&mut message, &format!(",{}", "s".repeat(args.len())));
+    for arg in args {
+        push_string(&mut message, arg);
+    }
+    message
+}
+
+fn send(address: &str, args: &[&str]) {
+    let Some(sockets) = SOCKETS.get() else {
+        return;
+    };
+
+    let message = encode(address, args);
+    for socket in sockets {
+        // Like any OSC sender, fire and forget; nobody listening isn't an
+        // error.
+        let _ = socket.send(&message);
+    }
+}
+
+pub fn state(path: &str, lightset: &LightCommandSet) {
+    send(
+        "/qlight/state",
+        &[
+            path,
+            lightset.red.name(),
+            lightset.yellow.name(),
+            lightset.green.name(),
+            lightset.blue.name(),
+            lightset.white.name(),
+            lightset.sound.name(),
+        ],
+    );
+}
+
+pub fn error(path: Option<&str>, error: &dyn Display) {
+    send(
+        "/qlight/error",
+        &[path.unwrap_or_default(), &error.to_string()],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pads_strings_to_four_bytes() {
+        let cases: [(&str, &[u8]); 4] = [
+            ("", b"\0\0\0\0"),
+            ("abc", b"abc\0"),
+            ("abcd", b"abcd\0\0\0\0"),
+            ("abcde", b"abcde\0\0\0"),
+        ];
+        for (s, expected) in cases {
+            let mut encoded = Vec::new();
+            push_string(&mut encoded, s);
+            assert_eq!(encoded, expected, "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn encodes_string_messages() {
+        assert_eq!(
+            encode("/qlight/error", &["", "Timed out"]),
+            b"/qlight/error\0\0\0,ss\0\0\0\0\0Timed out\0\0\0"
+        );
+        assert_eq!(encode("/a", &[]), b"/a\0\0,\0\0\0");
+    }
+
+    #[test]
+    fn rejects_bad_destinations() {
+        assert_eq!(
+            parse_destination("127.0.0.1:53000").unwrap(),
+            SocketAddr::from(([127, 0, 0, 1], 53000))
+        );
+        assert!(parse_destination("127.0.0.1").is_err());
+        assert!(parse_destination("127.0.0.1:notaport").is_err());
+    }
+}
@@ -0,0 +1,100 @@
+//! Wire format spoken by the Q-Light ST series USB towers.
+//!
+//! The towers show up as a plain HID device and take a single 65 byte
+//! output report per command. Nothing is ever read back.
+//!
+//! | Byte   | Meaning                           |
+//! |--------|-----------------------------------|
+//! | 0      | Report ID, always [`REPORT_ID`]   |
+//! | 1      | Unused, always `0x00`             |
+//! | 2      | Red lamp mode                     |
+//! | 3      | Yellow lamp mode                  |
+//! | 4      | Green lamp mode                   |
+//! | 5      | Blue lamp mode                    |
+//! | 6      | White lamp mode                   |
+//! | 7      | Sound mode                        |
+//! | 8..=64 | Unused, always `0x00`             |
+//!
+//! Lamp modes are `0` off, `1` on, `2` blink and `3` leave unchanged.
+//! Sound modes are `0` off, `1`-`5` for the five built in noises and `6`
+//! leave unchanged.
+
+pub const VID: u16 = 0x04d8;
+pub const PID: u16 = 0xe73c;
+pub const REPORT_ID: u8 = 0x57;
+
+/// Length of an output report, including the report ID.
+pub const REPORT_LEN: usize = 65;
+
+pub type Report = [u8; REPORT_LEN];
+
+/// Byte offsets of each field within a [`Report`].
+pub mod offset {
+    pub const REPORT_ID: usize = 0;
+    pub const RED: usize = 2;
+    pub const YELLOW: usize = 3;
+    pub const GREEN: usize = 4;
+    pub const BLUE: usize = 5;
+    pub const WHITE: usize = 6;
+    pub const SOUND: usize = 7;
+}
+
+/// The meaningful bytes of a report, as raw protocol values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportLayout {
+    pub red: u8,
+    pub yellow: u8,
+    pub green: u8,
+    pub blue: u8,
+    pub white: u8,
+    pub sound: u8,
+}
+
+impl ReportLayout {
+    /// Writes the report into `data`, clearing any bytes left over from a
+    /// previous use of the buffer.
+    pub fn encode_into(&self, data: &mut Report) {
+        data.fill(0x0);
+        data[offset::REPORT_ID] = REPORT_ID;
+        data[offset::RED] = self.red;
+        data[offset::YELLOW] = self.yellow;
+        data[offset::GREEN] = self.green;
+        data[offset::BLUE] = self.blue;
+        data[offset::WHITE] = self.white;
+        data[offset::SOUND] = self.sound;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUT: ReportLayout = ReportLayout {
+        red: 0,
+        yellow: 1,
+        green: 2,
+        blue: 3,
+        white: 1,
+        sound: 5,
+    };
+
+    fn golden() -> Report {
+        let mut expected = [0x0; REPORT_LEN];
+        expected[..8].copy_from_slice(&[0x57, 0x00, 0, 1, 2, 3, 1, 5]);
+        expected
+    }
+
+    #[test]
+    fn encodes_fields_at_their_offsets() {
+        let mut data = [0x0; REPORT_LEN];
+        LAYOUT.encode_into(&mut data);
+        assert_eq!(data, golden());
+    }
+
+    #[test]
+    fn encode_into_clears_a_dirty_buffer() {
+        let mut data = [0xff; REPORT_LEN];
+        LAYOUT.encode_into(&mut data);
+        assert_eq!(data, golden());
+    }
+}
@@ -1,8 +1,10 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
 use hidapi::{DeviceInfo, HidApi, HidDevice, HidError};
 
-const VID: u16 = 0x04d8;
-const PID: u16 = 0xe73c;
-const REPORT_ID: u8 = 0x57;
+use crate::protocol::{Report, ReportLayout, PID, REPORT_LEN, VID};
 
 pub type LightCommand = (Color, LightMode);
 
@@ -18,6 +20,31 @@ impl std::fmt::Display for ParseError {
 
 impl std::error::Error for ParseError {}
 
+#[derive(Debug)]
+pub enum LightError {
+    Hid(HidError),
+    /// The device didn't respond within the given time.
+    Timeout(Duration),
+}
+
+impl std::fmt::Display for LightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LightError::Hid(e) => e.fmt(f),
+            LightError::Timeout(timeout) => write!(f, "Timed out after {:?}", timeout),
+        }
+    }
+}
+
+impl std::error::Error for LightError {}
+
+impl From<HidError> for LightError {
+    fn from(e: HidError) -> Self {
+        LightError::Hid(e)
+    }
+}
+
+/// Lamp colors, numbered by their byte offset in the report.
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum Color {
     Red = 2,
@@ -77,6 +104,17 @@ impl TryFrom<&str> for LightMode {
     }
 }
 
+impl LightMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightMode::Off => "off",
+            LightMode::On => "on",
+            LightMode::Blink => "blink",
+            LightMode::Ignore => "ignore",
+        }
+    }
+}
+
 impl Default for LightMode {
     fn default() -> Self {
         Self::Ignore
@@ -95,13 +133,27 @@ pub enum SoundMode {
     Ignore = 6,
 }
 
+impl SoundMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SoundMode::Off => "off",
+            SoundMode::Noise1 => "noise1",
+            SoundMode::Noise2 => "noise2",
+            SoundMode::Noise3 => "noise3",
+            SoundMode::Noise4 => "noise4",
+            SoundMode::Noise5 => "noise5",
+            SoundMode::Ignore => "ignore",
+        }
+    }
+}
+
 impl Default for SoundMode {
     fn default() -> Self {
         Self::Ignore
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct LightCommandSet {
     pub red: LightMode,
     pub yellow: LightMode,
@@ -111,6 +163,22 @@ pub struct LightCommandSet {
     pub sound: SoundMode,
 }
 
+/// Formats as `set` commands, e.g. `red:on yellow:off ... sound:off`.
+impl std::fmt::Display for LightCommandSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "red:{} yellow:{} green:{} blue:{} white:{} sound:{}",
+            self.red.name(),
+            self.yellow.name(),
+            self.green.name(),
+            self.blue.name(),
+            self.white.name(),
+            self.sound.name(),
+        )
+    }
+}
+
 impl LightCommandSet {
     pub fn default_off() -> Self {
         Self {
@@ -133,27 +201,189 @@ impl LightCommandSet {
         }
     }
 
-    fn to_report(&self) -> [u8; 65] {
-        let mut data: [u8; 65] = [0x0; 65];
-        data[0] = REPORT_ID;
-        data[2] = self.red as u8;
-        data[3] = self.yellow as u8;
-        data[4] = self.green as u8;
-        data[5] = self.blue as u8;
-        data[6] = self.white as u8;
-        data[7] = self.sound as u8;
+    /// Builder form of [`set`](Self::set).
+    pub fn with(mut self, color: Color, light_mode: LightMode) -> Self {
+        self.set(color, light_mode);
+        self
+    }
+
+    pub fn with_sound(mut self, sound: SoundMode) -> Self {
+        self.sound = sound;
+        self
+    }
+
+    fn write_report(&self, data: &mut Report) {
+        ReportLayout {
+            red: self.red as u8,
+            yellow: self.yellow as u8,
+            green: self.green as u8,
+            blue: self.blue as u8,
+            white: self.white as u8,
+            sound: self.sound as u8,
+        }
+        .encode_into(data)
+    }
+
+    /// The report on its own, for comparing in tests.
+    #[cfg(test)]
+    pub fn to_report(&self) -> Report {
+        let mut data = [0x0; REPORT_LEN];
+        self.write_report(&mut data);
         data
     }
 }
 
+/// What hidapi knows about a light from enumeration. The towers don't
+/// answer any feature report queries, so this is all there is.
+#[derive(Debug, Clone)]
+pub struct LightInfo {
+    /// Platform specific device path, which also identifies its USB port.
+    pub path: String,
+    pub serial: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Device release number (bcdDevice), usually the firmware version.
+    pub release: u16,
+    pub interface: i32,
+}
+
+impl LightInfo {
+    /// The release number as `major.minor`, decoded from BCD.
+    pub fn release_string(&self) -> String {
+        format!("{:x}.{:02x}", self.release >> 8, self.release & 0xff)
+    }
+}
+
+impl From<&DeviceInfo> for LightInfo {
+    fn from(info: &DeviceInfo) -> Self {
+        Self {
+            path: info.path().to_string_lossy().into_owned(),
+            // A missing serial can come back as an empty string.
+            serial: info
+                .serial_number()
+                .filter(|s| !s.is_empty())
+                .map(String::from),
+            manufacturer: info.manufacturer_string().map(String::from),
+            product: info.product_string().map(String::from),
+            release: info.release_number(),
+            interface: info.interface_number(),
+        }
+    }
+}
+
+/// Runs a HID call on its own thread and stops waiting for it after
+/// `timeout`. The call itself can't be cancelled, so one that hangs is left
+/// behind on a detached thread.
+fn with_deadline<T, F>(timeout: Duration, f: F) -> Result<T, LightError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, HidError> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(f());
+    });
+
+    match rx.recv_timeout(timeout) {
+        Ok(result) => Ok(result?),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(LightError::Timeout(timeout)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("HID call panicked"),
+    }
+}
+
+/// Owns a light's device on a thread of its own, so writes can be given up
+/// on without spawning a thread for each one. Writes are numbered so a late
+/// reply to one that already timed out isn't taken for the current one.
+struct Writer {
+    requests: mpsc::SyncSender<(u64, Report)>,
+    replies: mpsc::Receiver<(u64, Result<usize, HidError>)>,
+    sent: u64,
+    timeout: Duration,
+}
+
+impl Writer {
+    fn spawn(device: HidDevice, timeout: Duration) -> Self {
+        let (requests, pending) = mpsc::sync_channel::<(u64, Report)>(1);
+        let (results, replies) = mpsc::sync_channel(1);
+        // Runs until the light is dropped, taking `requests` with it.
+        thread::spawn(move || {
+            for (id, report) in pending {
+                if results.send((id, device.write(&report))).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            requests,
+            replies,
+            sent: 0,
+            timeout,
+        }
+    }
+
+    fn write(&mut self, report: &Report) -> Result<usize, LightError> {
+        self.sent += 1;
+        match self.requests.try_send((self.sent, *report)) {
+            Ok(()) => {}
+            // Still stuck on an earlier write, with another queued behind it.
+            Err(mpsc::TrySendError::Full(_)) => return Err(LightError::Timeout(self.timeout)),
+            Err(mpsc::TrySendError::Disconnected(_)) => panic!("HID writer panicked"),
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.replies.recv_timeout(remaining) {
+                Ok((id, result)) if id == self.sent => return Ok(result?),
+                Ok(_) => continue,
+                Err(mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(LightError::Timeout(self.timeout))
+                }
+                Err(mpsc::RecvTimeoutError::Disconnected) => panic!("HID writer panicked"),
+            }
+        }
+    }
+}
+
+enum Device {
+    Direct(HidDevice),
+    Writer(Writer),
+}
+
 pub struct Light {
-    device: HidDevice,
+    device: Device,
+    info: LightInfo,
+    report: Report,
 }
 
 impl Light {
-    pub fn new(device: HidDevice) -> Self {
+    /// Opens a light. With a `timeout`, opening and every later write give
+    /// up with [`LightError::Timeout`] if the device doesn't respond in time.
+    pub fn open(
+        hidapi: &'static HidApi,
+        info: &DeviceInfo,
+        timeout: Option<Duration>,
+    ) -> Result<Self, LightError> {
         // TODO: Should I check if this is the right type of device?
-        Self { device }
+        let device = match timeout {
+            Some(timeout) => {
+                let info = info.clone();
+                let device = with_deadline(timeout, move || info.open_device(hidapi))?;
+                Device::Writer(Writer::spawn(device, timeout))
+            }
+            None => Device::Direct(info.open_device(hidapi)?),
+        };
+
+        Ok(Self {
+            device,
+            info: LightInfo::from(info),
+            report: [0x0; REPORT_LEN],
+        })
+    }
+
+    pub fn info(&self) -> &LightInfo {
+        &self.info
     }
 
     pub fn get_devices(hidapi: &HidApi) -> impl Iterator<Item = &DeviceInfo> {
@@ -162,7 +392,89 @@ impl Light {
             .filter(|x| x.vendor_id() == VID && x.product_id() == PID)
     }
 
-    pub fn update(&self, light_set: &LightCommandSet) -> Result<usize, HidError> {
-        self.device.write(&light_set.to_report())
+    pub fn update(&mut self, light_set: &LightCommandSet) -> Result<usize, LightError> {
+        light_set.write_report(&mut self.report);
+
+        // A write that timed out earlier may still be holding the device,
+        // in which case this one waits behind it and times out too.
+        match &mut self.device {
+            Device::Direct(device) => Ok(device.write(&self.report)?),
+            Device::Writer(writer) => writer.write(&self.report),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Color; 5] = [
+        Color::Red,
+        Color::Yellow,
+        Color::Green,
+        Color::Blue,
+        Color::White,
+    ];
+
+    // Raw protocol values, spelled out rather than taken from the enums.
+    const LAMPS: [(LightMode, u8); 4] = [
+        (LightMode::Off, 0),
+        (LightMode::On, 1),
+        (LightMode::Blink, 2),
+        (LightMode::Ignore, 3),
+    ];
+    const SOUNDS: [(SoundMode, u8); 7] = [
+        (SoundMode::Off, 0),
+        (SoundMode::Noise1, 1),
+        (SoundMode::Noise2, 2),
+        (SoundMode::Noise3, 3),
+        (SoundMode::Noise4, 4),
+        (SoundMode::Noise5, 5),
+        (SoundMode::Ignore, 6),
+    ];
+
+    fn golden(lamps: [u8; 5], sound: u8) -> Report {
+        let mut expected = [0x0; REPORT_LEN];
+        expected[0] = 0x57;
+        expected[2..7].copy_from_slice(&lamps);
+        expected[7] = sound;
+        expected
+    }
+
+    #[test]
+    fn to_report_matches_golden_bytes_for_every_combination() {
+        let mut checked = 0;
+        for combination in 0..LAMPS.len().pow(5) {
+            let mut lightset = LightCommandSet::default();
+            let mut lamps = [0; 5];
+            for (i, color) in COLORS.iter().enumerate() {
+                let (mode, byte) = LAMPS[combination / LAMPS.len().pow(i as u32) % LAMPS.len()];
+                lightset.set(*color, mode);
+                lamps[i] = byte;
+            }
+            for (sound, byte) in SOUNDS {
+                lightset.sound = sound;
+                assert_eq!(lightset.to_report(), golden(lamps, byte), "{}", lightset);
+                checked += 1;
+            }
+        }
+        assert_eq!(checked, 4 * 4 * 4 * 4 * 4 * 7);
+    }
+
+    #[test]
+    fn defaults_leave_everything_unchanged() {
+        assert_eq!(LightCommandSet::default().to_report(), golden([3; 5], 6));
+        assert_eq!(
+            LightCommandSet::default_off().to_report(),
+            golden([0; 5], 0)
+        );
+    }
+
+    #[test]
+    fn builders_match_set() {
+        let built = LightCommandSet::default_off()
+            .with(Color::Blue, LightMode::Blink)
+            .with_sound(SoundMode::Noise3);
+        assert_eq!(built.to_report(), golden([0, 0, 0, 2, 0], 3));
     }
 }
@@ -0,0 +1,96 @@
+//! Files the CLI keeps between runs, and what they share: where they live,
+//! which command is running and how to print their timestamps.
+
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use anyhow::{bail, Result};
+
+static COMMAND: OnceLock<&'static str> = OnceLock::new();
+
+/// Records which subcommand this process is running, for history and stats.
+pub fn set_command(name: &'static str) {
+    let _ = COMMAND.set(name);
+}
+
+pub fn command() -> &'static str {
+    COMMAND.get().copied().unwrap_or("unknown")
+}
+
+/// The user running this process, as far as the environment says.
+pub fn user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Where state files go: `$QLIGHT_STATE_DIR` if set, otherwise the
+/// platform's usual place for per-user application state.
+pub fn dir() -> Result<PathBuf> {
+    let var = |name| std::env::var_os(name).filter(|v| !v.is_empty());
+
+    if let Some(dir) = var("QLIGHT_STATE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        if let Some(local) = var("LOCALAPPDATA") {
+            return Ok(PathBuf::from(local).join("qlight"));
+        }
+    } else if cfg!(target_os = "macos") {
+        if let Some(home) = var("HOME") {
+            return Ok(PathBuf::from(home).join("Library/Application Support/qlight"));
+        }
+    } else if let Some(state) = var("XDG_STATE_HOME") {
+        return Ok(PathBuf::from(state).join("qlight"));
+    } else if let Some(home) = var("HOME") {
+        return Ok(PathBuf::from(home).join(".local/state/qlight"));
+    }
+
+    bail!("Couldn't work out where to keep state, set QLIGHT_STATE_DIR")
+}
+
+/// Formats seconds since the UNIX epoch as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_time(secs: u64) -> String {
+    let (days, secs) = (secs / 86_400, secs % 86_400);
+
+    // Howard Hinnant's days_from_civil, run backwards.
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_utc_times() {
+        let cases = [
+            (0, "1970-01-01 00:00:00"),
+            (951_782_400, "2000-02-29 00:00:00"),
+            (1_700_000_000, "2023-11-14 22:13:20"),
+            (4_107_542_399, "2100-02-28 23:59:59"),
+            (4_107_542_400, "2100-03-01 00:00:00"),
+        ];
+        for (secs, expected) in cases {
+            assert_eq!(format_time(secs), expected, "{}", secs);
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Opt-in usage counters, for reports like "the alarm light fired 212 times
+//! last quarter". Nothing is counted until `qlight stats --reset` creates
+//! `stats` in the [state directory](state::dir), and nothing ever leaves
+//! the machine. The file holds one counter per line:
+//!
+//! ```text
+//! since 1700000000
+//! commands.set 12
+//! errors 1
+//! lamps.red 7
+//! runtime_ms 3600000
+//! writes 40
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use clap::Parser;
+
+use crate::qlight::{LightCommandSet, LightMode};
+use crate::state;
+
+const FILE: &str = "stats";
+
+static WARNED: AtomicBool = AtomicBool::new(false);
+
+/// When running time was last added to the file.
+static COUNTED_UNTIL: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Show or reset the local usage counters
+///
+/// Counting is off until --reset is first run. The counters cover commands
+/// run, states written, how often each color was turned on, commands that
+/// failed and time spent running, which for watchers is counted up to their
+/// last write.
+#[derive(Parser, Debug)]
+pub struct StatsArgs {
+    /// Start counting again from zero, turning counting on if it was off.
+    #[arg(long, conflicts_with = "stop")]
+    reset: bool,
+
+    /// Stop counting and delete the counters.
+    #[arg(long)]
+    stop: bool,
+}
+
+type Counters = BTreeMap<String, u64>;
+
+fn parse(contents: &str) -> Counters {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (name, count) = line.split_once(' ')?;
+            Some((name.to_string(), count.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+fn format(counters: &Counters) -> String {
+    counters
+        .iter()
+        .map(|(name, count)| format!("{} {}\n", name, count))
+        .collect()
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Adds `counts` and the running time since the last call to the counters,
+/// if counting is on.
+fn add(counts: &[(&str, u64)]) -> Result<()> {
+    let elapsed = {
+        let mut counted_until = COUNTED_UNTIL.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        let elapsed = counted_until.map_or(0, |then| (now - then).as_millis() as u64);
+        *counted_until = Some(now);
+        elapsed
+    };
+
+    // Without a state directory there can't be a stats file either.
+    let Ok(dir) = state::dir() else {
+        return Ok(());
+    };
+    let path = dir.join(FILE);
+    let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {}", path.display())),
+    };
+
+    // Other qlight processes may be counting at the same time.
+    file.lock()?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    let mut counters = parse(&contents);
+    for (name, count) in counts.iter().copied().chain([("runtime_ms", elapsed)]) {
+        let counter = counters.entry(name.to_string()).or_default();
+        *counter = counter.saturating_add(count);
+    }
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(format(&counters).as_bytes())?;
+    Ok(())
+}
+
+/// Like history, counting shouldn't ever get in the way of the lights.
+fn add_or_warn(counts: &[(&str, u64)]) {
+    if let Err(e) = add(counts) {
+        if !WARNED.swap(true, Ordering::Relaxed) {
+            eprintln!("Not counting stats: {:#}", e);
+        }
+    }
+}
+
+/// Counts the command this process is running.
+pub fn start() {
+    let command = format!("commands.{}", state::command());
+    add_or_warn(&[(&command, 1)]);
+}
+
+/// Counts a state written to a light.
+pub fn record(lightset: &LightCommandSet) {
+    let lamps = [
+        ("lamps.red", lightset.red),
+        ("lamps.yellow", lightset.yellow),
+        ("lamps.green", lightset.green),
+        ("lamps.blue", lightset.blue),
+        ("lamps.white", lightset.white),
+    ];
+    let mut counts = vec![("writes", 1)];
+    for (name, mode) in lamps {
+        if mode == LightMode::On || mode == LightMode::Blink {
+            counts.push((name, 1));
+        }
+    }
+    add_or_warn(&counts);
+}
+
+/// Counts the rest of the running time, and whether the command failed.
+pub fn finish(failed: bool) {
+    add_or_warn(&[("errors", u64::from(failed))]);
+}
+
+fn format_runtime(ms: u64) -> String {
+    let secs = ms / 1000;
+    format!(
+        "{}h {:02}m {:02}s",
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+pub fn stats(args: StatsArgs) -> Result<()> {
+    let dir = state::dir()?;
+    let path = dir.join(FILE);
+
+    if args.stop {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to delete {}", path.display()))
+            }
+            _ => Ok(()),
+        };
+    }
+    if args.reset {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+        let counters = Counters::from([("since".to_string(), now_secs())]);
+        let mut file =
+            File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+        file.write_all(format(&counters).as_bytes())?;
+        return Ok(());
+    }
+
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("Not counting, run `qlight stats --reset` to start");
+            return Ok(());
+        }
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    let counters = parse(&contents);
+    let mut stdout = std::io::stdout().lock();
+    if let Some(since) = counters.get("since") {
+        writeln!(stdout, "Counting since {} UTC", state::format_time(*since))?;
+    }
+    for (name, count) in &counters {
+        match name.as_str() {
+            "since" => {}
+            "runtime_ms" => writeln!(stdout, "{:<20} {}", "runtime", format_runtime(*count))?,
+            _ => writeln!(stdout, "{:<20} {}", name, count)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_counters() {
+        let contents = "commands.set 12\nlamps.red 7\nsince 1700000000\nwrites 40\n";
+        let counters = parse(contents);
+        assert_eq!(counters.get("lamps.red"), Some(&7));
+        assert_eq!(format(&counters), contents);
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        let counters = parse("writes 3\ngarbage\nerrors lots\n\nlamps.red -1\n");
+        assert_eq!(format(&counters), "writes 3\n");
+    }
+
+    #[test]
+    fn formats_runtime() {
+        assert_eq!(format_runtime(999), "0h 00m 00s");
+        assert_eq!(format_runtime(3_723_000), "1h 02m 03s");
+        assert_eq!(format_runtime(100 * 3_600_000), "100h 00m 00s");
+    }
+}
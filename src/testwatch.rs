@@ -0,0 +1,380 @@
+use std::io::BufRead;
+
+use anyhow::Result;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, ValueEnum};
+
+use crate::qlight::{Color, LightCommandSet, LightMode};
+use crate::verbosity::status;
+use crate::{update_all, Picker};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, ValueEnum)]
+pub enum Runner {
+    /// cargo test, on its own or under cargo watch
+    Cargo,
+    /// jest, including --watch
+    Jest,
+    /// pytest, including pytest-watch
+    Pytest,
+    /// Match --running/--pass/--fail text yourself
+    Custom,
+}
+
+/// Follow test runner output on stdin: yellow while running, green on
+/// pass, red on failure
+///
+/// For example `cargo watch -x test 2>&1 | qlight test-watch --all --runner cargo`.
+/// The input is echoed to stderr so the test output stays visible.
+#[derive(Parser, Debug)]
+pub struct TestWatchArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// Which test runner's output to expect.
+    #[arg(long, value_enum)]
+    runner: Runner,
+
+    /// With `--runner custom`, text marking the start of a run. Without it,
+    /// a failure only shows until the next pass.
+    #[arg(long, value_name = "TEXT")]
+    running: Option<String>,
+
+    /// With `--runner custom`, text marking a passing run.
+    #[arg(long, value_name = "TEXT")]
+    pass: Option<String>,
+
+    /// With `--runner custom`, text marking a failing run.
+    #[arg(long, value_name = "TEXT")]
+    fail: Option<String>,
+
+    /// Blink red on failure instead of holding it.
+    #[arg(long)]
+    blink: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Outcome {
+    Running,
+    Pass,
+    Fail,
+}
+
+/// Removes ANSI color and cursor escapes, which most runners add when
+/// forced to use color.
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // ESC [ parameters... final byte in @..=~
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+impl TestWatchArgs {
+    fn classify(&self, line: &str) -> Option<Outcome> {
+        let line = line.trim();
+        match self.runner {
+            Runner::Cargo => {
+                if line.starts_with("[Running") || line.starts_with("Compiling ") {
+                    Some(Outcome::Running)
+                } else if line.starts_with("test result: FAILED")
+                    || line.starts_with("error:")
+                    || line.starts_with("error[")
+                {
+                    Some(Outcome::Fail)
+                } else if line.starts_with("test result: ok") {
+                    Some(Outcome::Pass)
+                } else {
+                    None
+                }
+            }
+            Runner::Jest => {
+                if line.starts_with("Determining test suites to run") || line.starts_with("RUNS ") {
+                    Some(Outcome::Running)
+                } else if line.starts_with("FAIL ")
+                    || (line.starts_with("Tests:") && line.contains("failed"))
+                {
+                    Some(Outcome::Fail)
+                } else if line.starts_with("Tests:") {
+                    Some(Outcome::Pass)
+                } else {
+                    None
+                }
+            }
+            Runner::Pytest => {
+                // ===== test session starts =====
+                // ===== 1 failed, 3 passed in 0.12s =====
+                if !line.starts_with('=') {
+                    None
+                } else if line.contains("test session starts") {
+                    Some(Outcome::Running)
+                } else if line.contains(" failed") || line.contains(" error") {
+                    Some(Outcome::Fail)
+                } else if line.contains(" passed")
+                    || line.contains(" xfailed")
+                    || line.contains(" skipped")
+                {
+                    Some(Outcome::Pass)
+                } else {
+                    None
+                }
+            }
+            Runner::Custom => {
+                let matches =
+                    |text: &Option<String>| text.as_deref().is_some_and(|t| line.contains(t));
+                if matches(&self.running) {
+                    Some(Outcome::Running)
+                } else if matches(&self.fail) {
+                    Some(Outcome::Fail)
+                } else if matches(&self.pass) {
+                    Some(Outcome::Pass)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn lightset(&self, outcome: Outcome) -> LightCommandSet {
+        let mut lightset = LightCommandSet::default_off();
+        match outcome {
+            Outcome::Running => lightset.set(Color::Yellow, LightMode::On),
+            Outcome::Pass => lightset.set(Color::Green, LightMode::On),
+            Outcome::Fail if self.blink => lightset.set(Color::Red, LightMode::Blink),
+            Outcome::Fail => lightset.set(Color::Red, LightMode::On),
+        }
+        lightset
+    }
+
+    /// Runners with several test binaries or suites report each one, so
+    /// once something has failed keep red until the next run starts. A
+    /// custom runner without --running never says when that is, so there
+    /// every result stands on its own.
+    fn sticky(&self, shown: Option<Outcome>, outcome: Outcome) -> Outcome {
+        let marks_runs = self.runner != Runner::Custom || self.running.is_some();
+        if marks_runs && shown == Some(Outcome::Fail) && outcome == Outcome::Pass {
+            Outcome::Fail
+        } else {
+            outcome
+        }
+    }
+}
+
+pub fn test_watch(args: TestWatchArgs) -> Result<()> {
+    if args.runner == Runner::Custom
+        && args.running.is_none()
+        && args.pass.is_none()
+        && args.fail.is_none()
+    {
+        crate::Args::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                "--runner custom needs at least one of --running, --pass or --fail",
+            )
+            .exit();
+    }
+
+    let mut lights = args.picker.open()?;
+
+    let mut shown = None;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        status!("{}", line);
+
+        let Some(outcome) = args.classify(&strip_ansi(&line)) else {
+            continue;
+        };
+        let outcome = args.sticky(shown, outcome);
+
+        if shown != Some(outcome) {
+            update_all(&mut lights, &args.lightset(outcome))?;
+            shown = Some(outcome);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(runner: &str) -> TestWatchArgs {
+        TestWatchArgs::try_parse_from(["test-watch", "--all", "--runner", runner]).unwrap()
+    }
+
+    /// Feeds `output` through the same steps as `test_watch` and returns
+    /// what would be showing at the end.
+    fn replay(args: &TestWatchArgs, output: &str) -> Option<Outcome> {
+        let mut shown = None;
+        for line in output.lines() {
+            if let Some(outcome) = args.classify(&strip_ansi(line)) {
+                shown = Some(args.sticky(shown, outcome));
+            }
+        }
+        shown
+    }
+
+    #[test]
+    fn strips_colors() {
+        let line = "\x1b[1m\x1b[32m   Compiling\x1b[0m qlight v0.0.1";
+        assert_eq!(strip_ansi(line), "   Compiling qlight v0.0.1");
+        let line = "test result: \x1b[31mFAILED\x1b[0m. 1 passed; 1 failed";
+        assert_eq!(strip_ansi(line), "test result: FAILED. 1 passed; 1 failed");
+        assert_eq!(strip_ansi("\x1b[2K\x1b[1Gplain"), "plain");
+        assert_eq!(strip_ansi("no escapes"), "no escapes");
+    }
+
+    #[test]
+    fn classifies_cargo_lines() {
+        let args = args("cargo");
+        let cases = [
+            ("[Running 'cargo test']", Some(Outcome::Running)),
+            ("   Compiling qlight v0.0.1 (/src/qlight)", Some(Outcome::Running)),
+            ("test result: ok. 12 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s", Some(Outcome::Pass)),
+            ("test result: FAILED. 11 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s", Some(Outcome::Fail)),
+            ("error[E0308]: mismatched types", Some(Outcome::Fail)),
+            ("error: could not compile `qlight` (bin \"qlight\" test) due to 1 previous error", Some(Outcome::Fail)),
+            ("test protocol::tests::encodes_fields_at_their_offsets ... ok", None),
+            ("     Running unittests src/main.rs (target/debug/deps/qlight-0123)", None),
+        ];
+        for (line, outcome) in cases {
+            assert_eq!(args.classify(line), outcome, "{}", line);
+        }
+    }
+
+    #[test]
+    fn cargo_failure_sticks_through_doc_tests() {
+        let output = "\
+   Compiling qlight v0.0.1 (/src/qlight)
+     Running unittests src/lib.rs (target/debug/deps/qlight-0123)
+test result: FAILED. 11 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s
+   Doc-tests qlight
+test result: ok. 3 passed; 0 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.20s
+";
+        assert_eq!(replay(&args("cargo"), output), Some(Outcome::Fail));
+
+        let rerun = format!(
+            "{}[Running 'cargo test']\ntest result: ok. 12 passed; 0 failed\n",
+            output
+        );
+        assert_eq!(replay(&args("cargo"), &rerun), Some(Outcome::Pass));
+    }
+
+    #[test]
+    fn classifies_jest_lines() {
+        let args = args("jest");
+        let cases = [
+            ("Determining test suites to run...", Some(Outcome::Running)),
+            ("RUNS  src/app.test.js", Some(Outcome::Running)),
+            ("FAIL src/app.test.js", Some(Outcome::Fail)),
+            ("PASS src/app.test.js", None),
+            (
+                "Tests:       1 failed, 4 passed, 5 total",
+                Some(Outcome::Fail),
+            ),
+            ("Tests:       5 passed, 5 total", Some(Outcome::Pass)),
+            ("Test Suites: 2 passed, 2 total", None),
+        ];
+        for (line, outcome) in cases {
+            assert_eq!(args.classify(line), outcome, "{}", line);
+        }
+    }
+
+    #[test]
+    fn classifies_pytest_lines() {
+        let args = args("pytest");
+        let cases = [
+            (
+                "============================= test session starts ==============================",
+                Some(Outcome::Running),
+            ),
+            (
+                "========================= 1 failed, 3 passed in 0.12s ==========================",
+                Some(Outcome::Fail),
+            ),
+            (
+                "=============================== 1 error in 0.05s ===============================",
+                Some(Outcome::Fail),
+            ),
+            (
+                "===================== 3 passed, 1 xfailed in 0.10s =====================",
+                Some(Outcome::Pass),
+            ),
+            (
+                "============================== 2 xfailed in 0.03s ==============================",
+                Some(Outcome::Pass),
+            ),
+            (
+                "===================== 2 passed, 1 xpassed in 0.10s =====================",
+                Some(Outcome::Pass),
+            ),
+            (
+                "============================== 4 passed in 0.08s ===============================",
+                Some(Outcome::Pass),
+            ),
+            (
+                "=================================== FAILURES ===================================",
+                None,
+            ),
+            ("FAILED tests/test_app.py::test_add - assert 1 == 2", None),
+            (
+                "tests/test_app.py ..F.                                                   [100%]",
+                None,
+            ),
+        ];
+        for (line, outcome) in cases {
+            assert_eq!(args.classify(line), outcome, "{}", line);
+        }
+    }
+    #[test]
+    fn custom_runner_without_running_follows_each_result() {
+        let args = TestWatchArgs::try_parse_from([
+            "test-watch",
+            "--all",
+            "--runner",
+            "custom",
+            "--pass",
+            "PASS",
+            "--fail",
+            "FAIL",
+        ])
+        .unwrap();
+        assert_eq!(replay(&args, "FAIL\nPASS\nPASS\n"), Some(Outcome::Pass));
+        assert_eq!(replay(&args, "PASS\nFAIL\n"), Some(Outcome::Fail));
+        assert_eq!(replay(&args, "noise\n"), None);
+    }
+
+    #[test]
+    fn custom_runner_with_running_sticks_within_a_run() {
+        let args = TestWatchArgs::try_parse_from([
+            "test-watch",
+            "--all",
+            "--runner",
+            "custom",
+            "--running",
+            "START",
+            "--pass",
+            "PASS",
+            "--fail",
+            "FAIL",
+        ])
+        .unwrap();
+        assert_eq!(replay(&args, "START\nFAIL\nPASS\n"), Some(Outcome::Fail));
+        assert_eq!(
+            replay(&args, "START\nFAIL\nSTART\nPASS\n"),
+            Some(Outcome::Pass)
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+
+use crate::qlight::{Color, LightCommandSet, LightMode};
+use crate::verbosity::status;
+use crate::{parse_nonzero_duration, update_all, update_once, Picker};
+
+/// Show green, yellow or red depending on a numeric value
+///
+/// With no VALUE, values are read from stdin one per line and the lights
+/// follow along, so anything that can print a number can drive the tower.
+/// --file polls a sensor file such as hwmon's temp1_input instead.
+/// If --crit is below --warn, lower values are treated as worse.
+#[derive(Parser, Debug)]
+pub struct ThresholdArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// Show yellow once the value reaches this.
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_value)]
+    warn: f64,
+
+    /// Show red once the value reaches this.
+    #[arg(long, allow_negative_numbers = true, value_parser = parse_value)]
+    crit: f64,
+
+    /// Blink red instead of holding it.
+    #[arg(long)]
+    blink: bool,
+
+    /// Multiply every value by this before comparing, e.g. 0.001 to turn
+    /// hwmon millidegrees into degrees.
+    #[arg(long, default_value = "1", allow_negative_numbers = true, value_parser = parse_value)]
+    scale: f64,
+
+    /// Poll the value from this file instead of stdin, e.g.
+    /// /sys/class/hwmon/hwmon0/temp1_input.
+    #[arg(long, value_name = "PATH", conflicts_with = "value")]
+    file: Option<PathBuf>,
+
+    /// How often to re-read --file.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_duration, default_value = "5s")]
+    interval: Duration,
+
+    /// The value to show
+    #[arg(allow_negative_numbers = true, value_parser = parse_value)]
+    value: Option<f64>,
+}
+
+impl ThresholdArgs {
+    fn level(&self, value: f64) -> Level {
+        let thresholds = Thresholds {
+            warn: self.warn,
+            crit: self.crit,
+        };
+        thresholds.level(value * self.scale)
+    }
+}
+
+/// Parses a reading, turning away NaN and infinities which would otherwise
+/// compare as normal.
+pub fn parse_value(s: &str) -> Result<f64> {
+    match s.parse::<f64>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => bail!("Expected a number, got {}", s),
+    }
+}
+
+fn read_file(path: &Path) -> Result<f64> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    parse_value(contents.trim()).with_context(|| format!("Expected a number in {}", path.display()))
+}
+
+/// Ordered from best to worst, so the worst of several readings is `max`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl Level {
+    pub fn lightset(&self, blink: bool) -> LightCommandSet {
+        let mut lightset = LightCommandSet::default_off();
+        match self {
+            Level::Normal => lightset.set(Color::Green, LightMode::On),
+            Level::Warning => lightset.set(Color::Yellow, LightMode::On),
+            Level::Critical if blink => lightset.set(Color::Red, LightMode::Blink),
+            Level::Critical => lightset.set(Color::Red, LightMode::On),
+        }
+        lightset
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub warn: f64,
+    pub crit: f64,
+}
+
+impl Thresholds {
+    pub fn level(&self, value: f64) -> Level {
+        // Flip everything around when lower is worse so one comparison works
+        // for both directions.
+        let (value, warn, crit) = if self.crit < self.warn {
+            (-value, -self.warn, -self.crit)
+        } else {
+            (value, self.warn, self.crit)
+        };
+
+        if value >= crit {
+            Level::Critical
+        } else if value >= warn {
+            Level::Warning
+        } else {
+            Level::Normal
+        }
+    }
+}
+
+pub fn threshold(args: ThresholdArgs) -> Result<()> {
+    let mut lights = args.picker.open()?;
+
+    if let Some(value) = args.value {
+        return update_once(&mut lights, &args.level(value).lightset(args.blink));
+    }
+
+    let mut shown = None;
+    let mut show = |value: f64| -> Result<()> {
+        let level = args.level(value);
+        if shown != Some(level) {
+            update_all(&mut lights, &level.lightset(args.blink))?;
+            shown = Some(level);
+        }
+        Ok(())
+    };
+
+    if let Some(path) = &args.file {
+        let mut failing = false;
+        loop {
+            // Sensor files can fail now and then, e.g. hwmon returning EIO
+            // while the chip is busy, so keep polling through it.
+            match read_file(path) {
+                Ok(value) => {
+                    if failing {
+                        status!("{} is readable again", path.display());
+                        failing = false;
+                    }
+                    show(value)?;
+                }
+                Err(e) if !failing => {
+                    status!("{:#}, still trying", e);
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+            sleep(args.interval);
+        }
+    }
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Ok(value) = parse_value(line) else {
+            eprintln!("Ignoring non-numeric input: {}", line);
+            continue;
+        };
+        show(value)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(extra: &[&str]) -> ThresholdArgs {
+        let argv = ["threshold", "--all"].iter().chain(extra);
+        ThresholdArgs::try_parse_from(argv).unwrap()
+    }
+
+    #[test]
+    fn higher_is_worse() {
+        let args = args(&["--warn", "60", "--crit", "75"]);
+        assert_eq!(args.level(59.9), Level::Normal);
+        assert_eq!(args.level(60.0), Level::Warning);
+        assert_eq!(args.level(74.9), Level::Warning);
+        assert_eq!(args.level(75.0), Level::Critical);
+        assert_eq!(args.level(1000.0), Level::Critical);
+    }
+
+    #[test]
+    fn lower_is_worse_when_crit_is_below_warn() {
+        let args = args(&["--warn", "20", "--crit", "10"]);
+        assert_eq!(args.level(20.1), Level::Normal);
+        assert_eq!(args.level(20.0), Level::Warning);
+        assert_eq!(args.level(10.1), Level::Warning);
+        assert_eq!(args.level(10.0), Level::Critical);
+        assert_eq!(args.level(-5.0), Level::Critical);
+    }
+
+    #[test]
+    fn scale_applies_before_comparing() {
+        let args = args(&["--warn", "60", "--crit", "75", "--scale", "0.001"]);
+        assert_eq!(args.level(45_000.0), Level::Normal);
+        assert_eq!(args.level(60_000.0), Level::Warning);
+        assert_eq!(args.level(80_000.0), Level::Critical);
+    }
+
+    #[test]
+    fn rejects_non_finite_values() {
+        for input in ["NaN", "nan", "inf", "-inf", "infinity", "", "12abc"] {
+            assert!(parse_value(input).is_err(), "{}", input);
+        }
+        assert_eq!(parse_value("-3.5").unwrap(), -3.5);
+
+        let argvs: [&[&str]; 3] = [
+            &["--warn", "nan", "--crit", "1", "5"],
+            &["--warn", "1", "--crit", "2", "--scale", "inf"],
+            &["--warn", "1", "--crit", "2", "NaN"],
+        ];
+        for argv in argvs {
+            let argv = ["threshold", "--all"].iter().chain(argv);
+            assert!(ThresholdArgs::try_parse_from(argv).is_err());
+        }
+    }
+    #[test]
+    fn reads_sensor_files() {
+        let path = std::env::temp_dir().join(format!("qlight-threshold-{}", std::process::id()));
+
+        std::fs::write(&path, "45000\n").unwrap();
+        assert_eq!(read_file(&path).unwrap(), 45000.0);
+
+        std::fs::write(&path, "busy\n").unwrap();
+        assert!(read_file(&path).is_err());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_file(&path).is_err());
+    }
+}
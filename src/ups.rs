@@ -0,0 +1,187 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+
+use crate::qlight::{Color, LightCommandSet, LightMode, SoundMode};
+use crate::verbosity::status;
+use crate::{parse_nonzero_duration, update_all, Picker};
+
+const NUT_PORT: u16 = 3493;
+const NUT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Watch a UPS through Network UPS Tools and show its power state
+///
+/// Green while on mains, blinking yellow on battery and blinking red with
+/// the buzzer once the battery is low. Blinking white means upsd couldn't
+/// be reached or gave an answer that didn't make sense, and watching carries
+/// on until it's back.
+#[derive(Parser, Debug)]
+pub struct UpsArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// The UPS to watch, as `name@host[:port]` like upsc takes.
+    ups: String,
+
+    /// Time to wait between status checks.
+    #[arg(long, value_name = "DURATION", value_parser = parse_nonzero_duration, default_value = "5s")]
+    interval: Duration,
+
+    /// Don't sound the buzzer on low battery.
+    #[arg(long)]
+    no_beep: bool,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PowerState {
+    Online,
+    OnBattery,
+    LowBattery,
+    /// upsd couldn't be reached or its answer couldn't be read.
+    Unknown,
+}
+
+impl PowerState {
+    /// Reads the space separated flags of `ups.status`, e.g. `OB LB`.
+    fn from_status(status: &str) -> Result<Self> {
+        let flags: Vec<&str> = status.split_whitespace().collect();
+        if flags.contains(&"LB") {
+            Ok(PowerState::LowBattery)
+        } else if flags.contains(&"OB") {
+            Ok(PowerState::OnBattery)
+        } else if flags.contains(&"OL") {
+            Ok(PowerState::Online)
+        } else {
+            bail!("Unrecognised UPS status {}", status)
+        }
+    }
+
+    fn lightset(&self, beep: bool) -> LightCommandSet {
+        let mut lightset = LightCommandSet::default_off();
+        match self {
+            PowerState::Online => lightset.set(Color::Green, LightMode::On),
+            PowerState::OnBattery => lightset.set(Color::Yellow, LightMode::Blink),
+            PowerState::LowBattery => {
+                lightset.set(Color::Red, LightMode::Blink);
+                if beep {
+                    lightset.sound = SoundMode::Noise1;
+                }
+            }
+            PowerState::Unknown => lightset.set(Color::White, LightMode::Blink),
+        }
+        lightset
+    }
+}
+
+fn parse_ups(s: &str) -> (&str, String) {
+    match s.split_once('@') {
+        Some((name, host)) if host.contains(':') => (name, host.to_string()),
+        Some((name, host)) => (name, format!("{}:{}", host, NUT_PORT)),
+        None => (s, format!("localhost:{}", NUT_PORT)),
+    }
+}
+
+fn connect(address: &str) -> Result<TcpStream> {
+    let mut last_error = None;
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, NUT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(match last_error {
+        Some(e) => e.into(),
+        None => anyhow!("{} didn't resolve to any address", address),
+    })
+}
+
+fn read_status(name: &str, address: &str) -> Result<PowerState> {
+    let mut stream = connect(address)?;
+    stream.set_read_timeout(Some(NUT_TIMEOUT))?;
+    stream.set_write_timeout(Some(NUT_TIMEOUT))?;
+    writeln!(stream, "GET VAR {} ups.status", name)?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let _ = writeln!(stream, "LOGOUT");
+
+    // VAR <ups> ups.status "OL CHRG"
+    let Some(status) = line
+        .trim_end()
+        .strip_prefix(&format!("VAR {} ups.status ", name))
+    else {
+        bail!("upsd at {} answered: {}", address, line.trim_end());
+    };
+
+    PowerState::from_status(status.trim_matches('"'))
+}
+
+pub fn ups(args: UpsArgs) -> Result<()> {
+    let (name, address) = parse_ups(&args.ups);
+
+    let mut lights = args.picker.open()?;
+
+    let mut shown = None;
+    loop {
+        // Keep watching through upsd restarts and network blips, they're
+        // exactly when the tower needs to stay up.
+        let state = read_status(name, &address).unwrap_or_else(|e| {
+            if shown != Some(PowerState::Unknown) {
+                status!("{}: {:#}", args.ups, e);
+            }
+            PowerState::Unknown
+        });
+        if shown != Some(state) {
+            status!("{}: {:?}", args.ups, state);
+            update_all(&mut lights, &state.lightset(!args.no_beep))?;
+            shown = Some(state);
+        }
+        sleep(args.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_status_flags() {
+        let cases = [
+            ("OL", PowerState::Online),
+            ("OL CHRG", PowerState::Online),
+            ("OB DISCHRG", PowerState::OnBattery),
+            ("OB LB", PowerState::LowBattery),
+            ("LB OB DISCHRG", PowerState::LowBattery),
+        ];
+        for (status, state) in cases {
+            assert_eq!(
+                PowerState::from_status(status).unwrap(),
+                state,
+                "{}",
+                status
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_status() {
+        for status in ["", "BYPASS", "OFF", "ol"] {
+            assert!(PowerState::from_status(status).is_err(), "{}", status);
+        }
+    }
+
+    #[test]
+    fn parses_ups_names() {
+        assert_eq!(parse_ups("myups"), ("myups", "localhost:3493".to_string()));
+        assert_eq!(parse_ups("myups@nas"), ("myups", "nas:3493".to_string()));
+        assert_eq!(
+            parse_ups("myups@nas:3500"),
+            ("myups", "nas:3500".to_string())
+        );
+    }
+}
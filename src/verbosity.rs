@@ -0,0 +1,46 @@
+//! How much the CLI says on stderr, set once from `--quiet`/`--verbose`.
+//! Errors and warnings are always printed.
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Level {
+    Quiet = 0,
+    Normal = 1,
+    Verbose = 2,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Normal as u8);
+
+pub fn set(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn get() -> Level {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => Level::Quiet,
+        1 => Level::Normal,
+        _ => Level::Verbose,
+    }
+}
+
+/// Progress and status lines, hidden by `--quiet`.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::get() >= $crate::verbosity::Level::Normal {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Extra detail, only shown with `--verbose`.
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::get() >= $crate::verbosity::Level::Verbose {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use detail;
+pub(crate) use status;
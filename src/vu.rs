@@ -0,0 +1,180 @@
+use std::io::{ErrorKind, Read};
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::Parser;
+
+use crate::threshold::{parse_value, Thresholds};
+use crate::verbosity::detail;
+use crate::{parse_duration, update_all, Picker};
+
+/// How much audio goes into each level reading.
+const BLOCK: Duration = Duration::from_millis(20);
+
+/// Quietest level reported, so silence doesn't come out as -inf.
+const FLOOR_DBFS: f64 = -96.0;
+
+/// Show how loud an audio input is, like a giant VU meter
+///
+/// Reads raw signed 16-bit little endian PCM from stdin, so any capture
+/// tool can feed it, e.g. `arecord -q -f S16_LE -r 16000 -c 1 | qlight vu
+/// --all` on Linux or `sox -q -d -t raw -b 16 -e signed -r 16000 -c 1 - |
+/// qlight vu --all` on macOS. Levels are in dBFS, where 0 is as loud as the
+/// input can go.
+#[derive(Parser, Debug)]
+pub struct VuArgs {
+    #[command(flatten)]
+    picker: Picker,
+
+    /// Level to show yellow at, in dBFS.
+    #[arg(long, default_value = "-20", allow_negative_numbers = true, value_parser = parse_value)]
+    warn: f64,
+
+    /// Level to show red at, in dBFS.
+    #[arg(long, default_value = "-6", allow_negative_numbers = true, value_parser = parse_value)]
+    crit: f64,
+
+    /// Sample rate of the input.
+    #[arg(long, default_value_t = 16000, value_parser = clap::value_parser!(u32).range(1..))]
+    rate: u32,
+
+    /// Channels interleaved in the input. All of them count towards the
+    /// level.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    channels: u16,
+
+    /// How quickly the meter rises to a louder level.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "50ms")]
+    attack: Duration,
+
+    /// How quickly the meter falls back once it gets quieter.
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "500ms")]
+    release: Duration,
+
+    /// Blink red instead of holding it.
+    #[arg(long)]
+    blink: bool,
+}
+
+/// Root mean square level of `samples` in dBFS.
+fn rms_dbfs(samples: &[i16]) -> f64 {
+    if samples.is_empty() {
+        return FLOOR_DBFS;
+    }
+    let power = samples
+        .iter()
+        .map(|&s| (f64::from(s) / 32768.0).powi(2))
+        .sum::<f64>()
+        / samples.len() as f64;
+    (10.0 * power.log10()).max(FLOOR_DBFS)
+}
+
+/// Follows the measured level, rising with the attack time and falling
+/// with the release time, like the needle on a meter.
+struct Meter {
+    level: f64,
+    attack: f64,
+    release: f64,
+}
+
+impl Meter {
+    fn new(attack: Duration, release: Duration) -> Self {
+        // How much of the old level is left after one block.
+        let keep = |time: Duration| (-BLOCK.as_secs_f64() / time.as_secs_f64()).exp();
+        Self {
+            level: FLOOR_DBFS,
+            attack: keep(attack),
+            release: keep(release),
+        }
+    }
+
+    fn update(&mut self, measured: f64) -> f64 {
+        let keep = if measured > self.level {
+            self.attack
+        } else {
+            self.release
+        };
+        self.level = measured + (self.level - measured) * keep;
+        self.level
+    }
+}
+
+pub fn vu(args: VuArgs) -> Result<()> {
+    let thresholds = Thresholds {
+        warn: args.warn,
+        crit: args.crit,
+    };
+    let mut meter = Meter::new(args.attack, args.release);
+
+    let samples_per_block =
+        (u64::from(args.rate) * u64::from(args.channels) * BLOCK.as_millis() as u64 / 1000).max(1)
+            as usize;
+    let mut bytes = vec![0; samples_per_block * 2];
+    let mut samples = vec![0; samples_per_block];
+
+    let mut lights = args.picker.open()?;
+
+    let mut stdin = std::io::stdin().lock();
+    let mut shown = None;
+    loop {
+        match stdin.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+        for (sample, pair) in samples.iter_mut().zip(bytes.chunks_exact(2)) {
+            *sample = i16::from_le_bytes([pair[0], pair[1]]);
+        }
+
+        let level = meter.update(rms_dbfs(&samples));
+        let level = thresholds.level(level);
+        if shown != Some(level) {
+            detail!("Level {:?} at {:.1} dBFS", level, meter.level);
+            update_all(&mut lights, &level.lightset(args.blink))?;
+            // Sound flips levels far too often to keep every one in the
+            // history.
+            lights.recording = false;
+            shown = Some(level);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measures_rms_in_dbfs() {
+        assert_eq!(rms_dbfs(&[]), FLOOR_DBFS);
+        assert_eq!(rms_dbfs(&[0; 320]), FLOOR_DBFS);
+
+        let full_scale = [i16::MIN; 320];
+        assert!(rms_dbfs(&full_scale).abs() < 1e-9);
+
+        // A full scale square wave is 0 dBFS, half of it about -6.
+        let square: Vec<i16> = (0..320)
+            .map(|i| if i % 2 == 0 { 16384 } else { -16384 })
+            .collect();
+        assert!((rms_dbfs(&square) + 6.02).abs() < 0.01);
+    }
+
+    #[test]
+    fn meter_rises_with_attack_and_falls_with_release() {
+        let mut meter = Meter::new(Duration::from_millis(20), Duration::from_millis(200));
+        meter.level = -60.0;
+
+        // One block is one attack time constant, so it gets ~63% of the way.
+        let risen = meter.update(0.0);
+        assert!((risen - -60.0 * (-1.0f64).exp()).abs() < 1e-9);
+
+        // Release is ten times slower.
+        let fallen = meter.update(-60.0);
+        assert!((fallen - (-60.0 + (risen + 60.0) * (-0.1f64).exp())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn zero_attack_jumps_straight_there() {
+        let mut meter = Meter::new(Duration::ZERO, Duration::from_millis(500));
+        assert_eq!(meter.update(-3.0), -3.0);
+    }
+}